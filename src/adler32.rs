@@ -0,0 +1,10 @@
+const ADLER32_MODULUS: u32 = 65521;
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + byte as u32) % ADLER32_MODULUS;
+        (a, (b + a) % ADLER32_MODULUS)
+    });
+
+    (b << 16) | a
+}
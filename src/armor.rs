@@ -0,0 +1,149 @@
+//! ASCII armor: binary data as Base64 text between marker lines, with a CRC-24 checksum.
+
+use crate::crc24::crc24;
+use crate::error::DecodeError;
+
+const ARMOR_BEGIN: &str = "-----BEGIN DZIP DATA-----";
+const ARMOR_END: &str = "-----END DZIP DATA-----";
+const LINE_WIDTH: usize = 64;
+const CHECKSUM_PREFIX: char = '=';
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_PADDING: u8 = b'=';
+
+fn base64_encoding(data: &[u8]) -> String {
+    let mut answer = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| value | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                answer.push(BASE64_ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                answer.push(BASE64_PADDING as char);
+            }
+        }
+    }
+    answer
+}
+
+fn base64_decoding(text: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let text = text.strip_suffix(b"==").or_else(|| text.strip_suffix(b"=")).unwrap_or(text);
+    if text.len() % 4 == 1 {
+        return Err(DecodeError::InvalidHeader("invalid base64 length"));
+    }
+
+    let mut answer = Vec::with_capacity(text.len() / 4 * 3);
+    for chunk in text.chunks(4) {
+        let mut value = 0u32;
+        for (i, character) in chunk.iter().enumerate() {
+            let digit = BASE64_ALPHABET.iter().position(|x| x == character)
+                .ok_or(DecodeError::InvalidHeader("invalid base64 character"))?;
+            value |= (digit as u32) << (18 - 6 * i);
+        }
+        answer.extend(&value.to_be_bytes()[1..chunk.len()]);
+    }
+    Ok(answer)
+}
+
+/// Wraps the data in lines of Base64 between the BEGIN and END marker lines, the last line
+/// before the END marker is `=` followed by the Base64 of the CRC-24 of the data, as in
+/// OpenPGP armor (RFC 4880).
+pub fn armor_encoding(data: &[u8]) -> Vec<u8> {
+    let text = base64_encoding(data);
+
+    let mut answer = String::new();
+    answer.push_str(ARMOR_BEGIN);
+    answer.push('\n');
+    for line in text.as_bytes().chunks(LINE_WIDTH) {
+        answer.push_str(std::str::from_utf8(line).unwrap());
+        answer.push('\n');
+    }
+    answer.push(CHECKSUM_PREFIX);
+    answer.push_str(&base64_encoding(&crc24(data).to_be_bytes()[1..]));
+    answer.push('\n');
+    answer.push_str(ARMOR_END);
+    answer.push('\n');
+
+    answer.into_bytes()
+}
+
+fn find(data: &[u8], pattern: &str) -> Option<usize> {
+    data.windows(pattern.len()).position(|x| x == pattern.as_bytes())
+}
+
+fn is_text(data: &[u8]) -> bool {
+    std::str::from_utf8(data).is_ok_and(|x| x.chars().all(|c| !c.is_control() || c.is_ascii_whitespace()))
+}
+
+/// Only text may come before the BEGIN line. Binary data, such as a gzip or `.dzip` file
+/// whose content contains the marker, is not armor.
+pub fn is_armored(data: &[u8]) -> bool {
+    find(data, ARMOR_BEGIN).is_some_and(|x| is_text(&data[..x]))
+}
+
+/// Text around the markers is ignored, as well as any whitespace between them, so that armor
+/// indented in YAML or quoted in a message still decodes.
+pub fn armor_decoding(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let start = find(data, ARMOR_BEGIN).ok_or(DecodeError::InvalidHeader("no armor begin line"))? + ARMOR_BEGIN.len();
+    let end = find(&data[start..], ARMOR_END).ok_or(DecodeError::UnexpectedEnd)? + start;
+
+    let mut text = Vec::new();
+    let mut checksum = None;
+    for line in data[start..end].split(|x| *x == b'\n') {
+        let line = line.iter().copied().filter(|x| !x.is_ascii_whitespace()).collect::<Vec<u8>>();
+        match line.strip_prefix(&[CHECKSUM_PREFIX as u8]) {
+            Some(value) if value.len() == 4 => checksum = Some(base64_decoding(value)?),
+            _ if checksum.is_some() && !line.is_empty() =>
+                return Err(DecodeError::InvalidHeader("data after the armor checksum")),
+            _ => text.extend(line),
+        }
+    }
+
+    let answer = base64_decoding(&text)?;
+    let checksum = checksum.ok_or(DecodeError::InvalidHeader("no armor checksum"))?;
+    if u32::from_be_bytes([0, checksum[0], checksum[1], checksum[2]]) != crc24(&answer) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adler32::adler32;
+    use crate::test_data::random_bytes;
+
+
+    #[test]
+    fn armor_in_text_is_decoded() {
+        let mut data = b"Subject: data\n\n> ".to_vec();
+        data.extend(armor_encoding(b"hello"));
+        assert!(is_armored(&data));
+        assert_eq!(armor_decoding(&data).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn dzip_with_armored_content_is_not_armor() {
+        let mut original = armor_encoding(b"inner payload");
+        original.extend(random_bytes(60000, 1));
+        let compressed = crate::compress(&original);
+
+        assert!(find(&compressed, ARMOR_BEGIN).is_some());
+        assert!(!is_armored(&compressed));
+        assert_eq!(crate::decompress(&compressed).unwrap(), original);
+    }
+
+    #[test]
+    fn stored_zlib_with_armored_content_is_not_armor() {
+        let original = armor_encoding(b"inner payload");
+        let mut compressed = vec![0x78, 0x01, 0x01];
+        compressed.extend((original.len() as u16).to_le_bytes());
+        compressed.extend((!(original.len() as u16)).to_le_bytes());
+        compressed.extend(&original);
+        compressed.extend(adler32(&original).to_be_bytes());
+
+        assert!(!is_armored(&compressed));
+        assert_eq!(crate::decompress(&compressed).unwrap(), original);
+    }
+}
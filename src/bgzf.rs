@@ -0,0 +1,242 @@
+//! BGZF, the blocked gzip of samtools: gzip members of at most 64 KiB that record their own
+//! size, so that an index can point into the compressed file.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::bin_num::bin_to_bytes;
+use crate::crc32::crc32;
+use crate::error::DecodeError;
+use crate::gzip::{gzip_member_decoding, is_gzip, read_gzip_header, write_gzip_member, GzipHeader};
+use crate::{deflate_encoding, DeflateVariant};
+
+// the same chunk size as samtools, so that even a stored block fits into 64 KiB
+const BGZF_BLOCK_DATA_SIZE: usize = 0xff00;
+const BGZF_HEADER_SIZE: usize = 18;
+// the fixed part of a gzip header together with the length of the extra field
+const GZIP_EXTRA_START: usize = 12;
+const BGZF_SUBFIELD_ID: [u8; 2] = *b"BC";
+const OS_UNKNOWN: u8 = 255;
+
+const BGZF_EOF_BLOCK: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+fn bgzf_block_encoding(answer: &mut Vec<u8>, data: &[u8]) {
+    let compressed = bin_to_bytes(&deflate_encoding(data.to_vec(), DeflateVariant::Deflate));
+    let block_size = BGZF_HEADER_SIZE + compressed.len() + 8;
+
+    let mut extra = BGZF_SUBFIELD_ID.to_vec();
+    extra.extend(2u16.to_le_bytes());
+    extra.extend(((block_size - 1) as u16).to_le_bytes());
+
+    let header = GzipHeader {
+        mtime: 0,
+        extra: Some(extra),
+        name: None,
+        comment: None,
+        os: OS_UNKNOWN,
+    };
+    write_gzip_member(answer, &header, compressed.as_slice(), crc32(data), data.len() as u32);
+}
+
+/// Compresses the data in blocks of 65280 bytes and ends the file with the empty EOF block.
+pub fn bgzf_encoding(data: &[u8]) -> Vec<u8> {
+    let mut answer = Vec::new();
+    for chunk in data.chunks(BGZF_BLOCK_DATA_SIZE) {
+        bgzf_block_encoding(&mut answer, chunk);
+    }
+    answer.extend(BGZF_EOF_BLOCK);
+
+    answer
+}
+
+/// Finds BSIZE, the total block size minus one, among the subfields of the extra field.
+fn bgzf_block_size(extra: &[u8]) -> Option<usize> {
+    let mut index = 0;
+    while index + 4 <= extra.len() {
+        let length = u16::from_le_bytes([extra[index + 2], extra[index + 3]]) as usize;
+        if extra[index..index + 2] == BGZF_SUBFIELD_ID && length == 2 {
+            return extra.get(index + 4..index + 6).map(|x| u16::from_le_bytes([x[0], x[1]]) as usize + 1);
+        }
+        index += 4 + length;
+    }
+    None
+}
+
+pub(crate) fn has_bgzf_block_size(extra: &[u8]) -> bool {
+    bgzf_block_size(extra).is_some()
+}
+
+/// Returns true if the data starts with a gzip member carrying the BGZF block size.
+pub fn is_bgzf(data: &[u8]) -> bool {
+    read_gzip_header(data).is_ok_and(|(header, _)| header.extra.as_deref().is_some_and(has_bgzf_block_size))
+}
+
+/// Reads a BGZF file block by block. A position in the file is a virtual offset:
+/// the offset of the block in the compressed file shifted left by 16 bits, plus
+/// the offset inside the decompressed block.
+pub struct BgzfReader<R: Read + Seek> {
+    reader: R,
+    block: Vec<u8>,
+    block_position: usize,
+    next_block_offset: u64,
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Reads from the first block on.
+    pub fn new(reader: R) -> BgzfReader<R> {
+        BgzfReader {
+            reader,
+            block: Vec::new(),
+            block_position: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// Returns false at the end of the file.
+    fn read_block(&mut self, offset: u64) -> Result<bool, DecodeError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+
+        let mut block = Vec::new();
+        self.reader.by_ref().take(GZIP_EXTRA_START as u64).read_to_end(&mut block)?;
+        if block.is_empty() {
+            return Ok(false);
+        }
+        if block.len() < GZIP_EXTRA_START {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        if !is_gzip(block.as_slice()) {
+            return Err(DecodeError::InvalidHeader("not a bgzf block"));
+        }
+
+        let extra_size = u16::from_le_bytes([block[10], block[11]]) as usize;
+        block.resize(GZIP_EXTRA_START + extra_size, 0);
+        self.reader.read_exact(&mut block[GZIP_EXTRA_START..])?;
+        let block_size = bgzf_block_size(&block[GZIP_EXTRA_START..])
+            .ok_or(DecodeError::InvalidHeader("not a bgzf block"))?;
+
+        if block_size < block.len() {
+            return Err(DecodeError::InvalidHeader("invalid bgzf block size"));
+        }
+        let rest = block_size - block.len();
+        self.reader.by_ref().take(rest as u64).read_to_end(&mut block)?;
+
+        let (_, data, _) = gzip_member_decoding(block.as_slice())?;
+        self.block = data;
+        self.block_position = 0;
+        self.next_block_offset = offset + block_size as u64;
+
+        Ok(true)
+    }
+
+    /// Moves to a virtual offset. The block must start at the offset, the position inside it
+    /// may be at its end.
+    pub fn seek_virtual(&mut self, virtual_offset: u64) -> Result<(), DecodeError> {
+        let block_offset = virtual_offset >> 16;
+        let position = (virtual_offset & 0xffff) as usize;
+
+        if !self.read_block(block_offset)? {
+            self.block.clear();
+            self.next_block_offset = block_offset;
+        }
+        if position > self.block.len() {
+            return Err(DecodeError::InvalidHeader("virtual offset is outside of the block"));
+        }
+        self.block_position = position;
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.block_position == self.block.len() {
+            let is_read = self.read_block(self.next_block_offset)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            if !is_read {
+                return Ok(0);
+            }
+        }
+
+        let size = buf.len().min(self.block.len() - self.block_position);
+        buf[..size].copy_from_slice(&self.block[self.block_position..self.block_position + size]);
+        self.block_position += size;
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gzip::{gzip_encoding, gzip_recompression};
+    use crate::test_data::random_bytes;
+    use std::io::Cursor;
+
+    fn test_data() -> Vec<u8> {
+        let mut data = b"chr1\t100\t200\n".repeat(5000);
+        data.extend(random_bytes(100000, 1));
+        data
+    }
+
+    /// The offsets of the blocks, walked by their BSIZE.
+    fn block_offsets(compressed: &[u8]) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while offset < compressed.len() {
+            offsets.push(offset);
+            let (header, _) = read_gzip_header(&compressed[offset..]).unwrap();
+            offset += bgzf_block_size(&header.extra.unwrap()).unwrap();
+        }
+        assert_eq!(offset, compressed.len());
+        offsets
+    }
+
+    #[test]
+    fn blocks_carry_their_size() {
+        let data = test_data();
+        let compressed = bgzf_encoding(&data);
+        let offsets = block_offsets(&compressed);
+        assert_eq!(offsets.len(), data.len().div_ceil(BGZF_BLOCK_DATA_SIZE) + 1);
+
+        let mut decoded = Vec::new();
+        for (&start, &end) in offsets.iter().zip(offsets[1..].iter().chain([&compressed.len()])) {
+            let (_, block, size) = gzip_member_decoding(&compressed[start..end]).unwrap();
+            assert_eq!(size, end - start);
+            decoded.extend(block);
+        }
+        assert_eq!(decoded, data);
+        assert!(compressed.ends_with(&BGZF_EOF_BLOCK));
+    }
+
+    #[test]
+    fn virtual_offsets_are_seeked() {
+        let data = test_data();
+        let compressed = bgzf_encoding(&data);
+        let offsets = block_offsets(&compressed);
+
+        let mut reader = BgzfReader::new(Cursor::new(compressed.as_slice()));
+        for (block, within_block) in [(0, 0), (0, 1234), (1, 0), (1, BGZF_BLOCK_DATA_SIZE - 1), (2, 7)] {
+            reader.seek_virtual(((offsets[block] as u64) << 16) | within_block as u64).unwrap();
+            let mut answer = Vec::new();
+            reader.read_to_end(&mut answer).unwrap();
+            assert_eq!(answer, &data[block * BGZF_BLOCK_DATA_SIZE + within_block..], "block {} at {}", block, within_block);
+        }
+
+        // the end of the file, a position past the end of a block and an offset inside a block
+        reader.seek_virtual((compressed.len() as u64) << 16).unwrap();
+        assert_eq!(reader.read(&mut [0; 8]).unwrap(), 0);
+        assert!(reader.seek_virtual(((offsets[1] as u64) << 16) | (BGZF_BLOCK_DATA_SIZE as u64 + 1)).is_err());
+        assert!(reader.seek_virtual(((offsets[1] as u64 + 1) << 16) | 10).is_err());
+    }
+
+    #[test]
+    fn bgzf_is_not_recompressed() {
+        let data = b"chr1\t100\t200\n".repeat(10000);
+        let compressed = bgzf_encoding(&data);
+        assert!(is_bgzf(&compressed));
+        assert!(!is_bgzf(&gzip_encoding(data, &GzipHeader::default())));
+        assert!(gzip_recompression(&compressed).is_err());
+    }
+}
@@ -0,0 +1,49 @@
+//! The stub of the self-extracting archives written by `dzip sfx`. It extracts the zip
+//! payload appended to its own executable and holds nothing else, so that the archives stay
+//! small.
+
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use dzip::sfx::read_sfx_payload_bounds;
+use dzip::zip::ZipReader;
+use dzip::DecodeError;
+
+/// Returns false if any of the entries was not extracted, the others are extracted anyway.
+fn extract(executable: &Path, destination: &Path) -> Result<bool, DecodeError> {
+    let mut file = File::open(executable)?;
+    let (start, end) = read_sfx_payload_bounds(&mut file)?;
+    let mut reader = ZipReader::new(file, start, end);
+    let records = reader.read_central_directory()?;
+
+    fs::create_dir_all(destination)?;
+    let mut is_successful = true;
+    for record in &records {
+        if let Err(e) = reader.extract_entry(record, destination) {
+            println!("Unable to extract {}: {}", record.name, e);
+            is_successful = false;
+        }
+    }
+
+    Ok(is_successful)
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let destination = match args.as_slice() {
+        [flag, path] if flag == "-C" => PathBuf::from(path),
+        [] => PathBuf::from("."),
+        _ => {
+            println!("Incorrect data");
+            return;
+        }
+    };
+
+    let executable = std::env::current_exe().expect("unable to find the executable");
+    match extract(&executable, &destination) {
+        Ok(true) => println!("Successful"),
+        Ok(false) => {}
+        Err(e) => println!("Unable to read archive: {}", e),
+    }
+}
@@ -7,7 +7,7 @@ pub fn to_bin(mut val: i32) -> Vec<bool> {
     }
     answer.push(val == 1);
     answer.reverse();
-    return answer;
+    answer
 }
 
 
@@ -21,13 +21,26 @@ pub fn to_const_size_bin(val: i32, size: i32) -> Vec<bool> {
     answer
 }
 
-pub fn bin_to_num(data: &[bool]) -> i32 {
-    let mut result = 0;
-    for i in 0..data.len() {
-        if data[i] {
-            result += i32::pow(2, (data.len() - 1 - i) as u32);
-        }
-    }
+pub fn to_const_size_lsb_bin(val: i32, size: i32) -> Vec<bool> {
+    let mut answer = to_const_size_bin(val, size);
+    answer.reverse();
+    answer
+}
+
+pub fn lsb_bin_to_num(data: &[bool]) -> i32 {
+    data.iter().rev().fold(0, |result, &bit| (result << 1) | bit as i32)
+}
+
+pub fn bin_to_bytes(value: &[bool]) -> Vec<u8> {
+    value.chunks(8).map(|byte| {
+        byte.iter().rev().fold(0, |result, &bit| (result << 1) | bit as u8)
+    }).collect()
+}
 
-    result
-}
\ No newline at end of file
+pub fn bytes_to_bin(data: &[u8]) -> Vec<bool> {
+    data.iter().flat_map(|byte| (0..8).map(move |i| byte & (1 << i) != 0)).collect()
+}
+
+pub fn bin_to_num(data: &[bool]) -> i32 {
+    data.iter().fold(0, |result, &bit| (result << 1) | bit as i32)
+}
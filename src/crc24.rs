@@ -0,0 +1,16 @@
+// the OpenPGP CRC-24 from RFC 4880, section 6.1
+const CRC24_INIT: u32 = 0xb704ce;
+const CRC24_POLYNOMIAL: u32 = 0x1864cfb;
+
+pub fn crc24(data: &[u8]) -> u32 {
+    data.iter().fold(CRC24_INIT, |crc, &byte| {
+        let mut crc = crc ^ ((byte as u32) << 16);
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x1000000 != 0 {
+                crc ^= CRC24_POLYNOMIAL;
+            }
+        }
+        crc
+    }) & 0xffffff
+}
@@ -0,0 +1,512 @@
+//! The `.dzip` format: a header with the size and CRC-32 of the data followed by a deflate
+//! stream, optionally split into frames that can be checked or read separately.
+
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::adler32::adler32;
+use crate::bin_num::bin_to_bytes;
+use crate::crc32::crc32;
+use crate::error::DecodeError;
+use crate::legacy::legacy_decoding;
+use crate::{deflate_decoding, deflate_encoding_with_dictionary, deflate_stream_decoding_with_dictionary,
+            DeflateVariant};
+
+// the first byte has block type 11, so a header can never be mistaken for a headerless deflate stream
+const DZIP_MAGIC: [u8; 4] = [0xd7, b'D', b'Z', b'P'];
+const DZIP_VERSION: u8 = 1;
+pub(crate) const DZIP_HEADER_SIZE: usize = 18;
+
+pub(crate) const DICTIONARY_ID_SIZE: usize = 4;
+
+const FLAG_DEFLATE64: u8 = 0x01;
+const FLAG_DICTIONARY: u8 = 0x02;
+const FLAG_SEEKABLE: u8 = 0x04;
+const FLAG_CHECKSUMS: u8 = 0x08;
+const KNOWN_FLAGS: u8 = FLAG_DEFLATE64 | FLAG_DICTIONARY | FLAG_SEEKABLE | FLAG_CHECKSUMS;
+
+pub(crate) const FRAME_HEADER_SIZE: usize = 12;
+
+const INDEX_MAGIC: [u8; 4] = *b"DZPX";
+const INDEX_ENTRY_SIZE: usize = 8;
+const INDEX_TRAILER_SIZE: usize = 8;
+/// The frame size of the `dzip` tool, 1 MiB of data.
+pub const DEFAULT_FRAME_SIZE: usize = 1 << 20;
+
+pub(crate) struct DzipHeader {
+    pub version: u8,
+    pub flags: u8,
+    pub size: u64,
+    pub crc: u32,
+    /// Adler-32 of the preset dictionary, present only with `FLAG_DICTIONARY`.
+    pub dictionary_id: Option<u32>,
+}
+
+impl DzipHeader {
+    pub fn header_size(&self) -> usize {
+        if self.dictionary_id.is_some() {
+            DZIP_HEADER_SIZE + DICTIONARY_ID_SIZE
+        } else {
+            DZIP_HEADER_SIZE
+        }
+    }
+
+    pub fn is_seekable(&self) -> bool {
+        self.flags & FLAG_SEEKABLE != 0
+    }
+
+    pub fn has_checksums(&self) -> bool {
+        self.flags & FLAG_CHECKSUMS != 0
+    }
+
+    pub fn variant(&self) -> DeflateVariant {
+        if self.flags & FLAG_DEFLATE64 != 0 {
+            DeflateVariant::Deflate64
+        } else {
+            DeflateVariant::Deflate
+        }
+    }
+}
+
+/// A frame of a seekable or checksummed file, an independent deflate stream.
+/// `compressed_size` includes the frame header of a checksummed file.
+#[derive(Debug, Clone)]
+pub(crate) struct DzipFrame {
+    pub compressed_offset: u64,
+    pub compressed_size: u32,
+    pub offset: u64,
+    pub size: u32,
+}
+
+/// Returns true if the data starts with the `.dzip` magic number.
+pub fn is_dzip(data: &[u8]) -> bool {
+    data.starts_with(&DZIP_MAGIC)
+}
+
+/// Returns true if the header announces a frame index at the end of the file.
+pub fn is_seekable_dzip(data: &[u8]) -> bool {
+    is_dzip(data) && data.len() > 5 && data[5] & FLAG_SEEKABLE != 0
+}
+
+fn write_dzip_header(answer: &mut Vec<u8>, header: &DzipHeader) {
+    answer.extend(DZIP_MAGIC);
+    answer.push(header.version);
+    answer.push(header.flags);
+    answer.extend(header.size.to_le_bytes());
+    answer.extend(header.crc.to_le_bytes());
+    if let Some(dictionary_id) = header.dictionary_id {
+        answer.extend(dictionary_id.to_le_bytes());
+    }
+}
+
+pub(crate) fn read_dzip_header(data: &[u8]) -> Result<DzipHeader, DecodeError> {
+    if !is_dzip(data) {
+        return Err(DecodeError::InvalidHeader("not a dzip file"));
+    }
+    if data.len() < DZIP_HEADER_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let mut header = DzipHeader {
+        version: data[4],
+        flags: data[5],
+        size: u64::from_le_bytes(data[6..14].try_into().unwrap()),
+        crc: u32::from_le_bytes(data[14..18].try_into().unwrap()),
+        dictionary_id: None,
+    };
+
+    if header.version != DZIP_VERSION {
+        return Err(DecodeError::UnsupportedVersion(header.version));
+    }
+    if header.flags & !KNOWN_FLAGS != 0 {
+        return Err(DecodeError::InvalidHeader("unknown flags"));
+    }
+    if header.flags & FLAG_DICTIONARY != 0 {
+        let dictionary_id = data.get(DZIP_HEADER_SIZE..DZIP_HEADER_SIZE + DICTIONARY_ID_SIZE)
+            .ok_or(DecodeError::UnexpectedEnd)?;
+        header.dictionary_id = Some(u32::from_le_bytes(dictionary_id.try_into().unwrap()));
+    }
+
+    Ok(header)
+}
+
+fn dzip_header_for(data: &[u8], dictionary: Option<&[u8]>, variant: DeflateVariant, frame_flags: u8)
+                   -> DzipHeader {
+    let mut flags = frame_flags;
+    if variant == DeflateVariant::Deflate64 {
+        flags |= FLAG_DEFLATE64;
+    }
+    if dictionary.is_some() {
+        flags |= FLAG_DICTIONARY;
+    }
+
+    DzipHeader {
+        version: DZIP_VERSION,
+        flags,
+        size: data.len() as u64,
+        crc: crc32(data),
+        dictionary_id: dictionary.map(adler32),
+    }
+}
+
+/// Compresses the data into one deflate stream. With a dictionary, back references may
+/// point into it and the header holds its Adler-32.
+pub fn dzip_encoding(data: Vec<u8>, dictionary: Option<&[u8]>, variant: DeflateVariant) -> Vec<u8> {
+    let header = dzip_header_for(data.as_slice(), dictionary, variant, 0);
+
+    let mut answer = Vec::new();
+    write_dzip_header(&mut answer, &header);
+    answer.extend(bin_to_bytes(&deflate_encoding_with_dictionary(data, dictionary.unwrap_or_default(), variant)));
+
+    answer
+}
+
+/// Splits the data into frames of `frame_size` bytes that are compressed independently
+/// (each one starts from the preset dictionary only).
+///
+/// With `has_checksums` every frame starts with a frame header: its compressed size
+/// (without the frame header), uncompressed size and CRC-32, all little-endian.
+/// With `is_seekable` the file ends with an index of the frames: the compressed and
+/// uncompressed size of every frame, then the number of frames and `INDEX_MAGIC`.
+/// Without either option this is the same as `dzip_encoding`.
+pub fn dzip_framed_encoding(data: Vec<u8>, dictionary: Option<&[u8]>, variant: DeflateVariant, frame_size: usize,
+                            is_seekable: bool, has_checksums: bool) -> Vec<u8> {
+    if !is_seekable && !has_checksums {
+        return dzip_encoding(data, dictionary, variant);
+    }
+
+    let mut frame_flags = 0;
+    if is_seekable {
+        frame_flags |= FLAG_SEEKABLE;
+    }
+    if has_checksums {
+        frame_flags |= FLAG_CHECKSUMS;
+    }
+    let header = dzip_header_for(data.as_slice(), dictionary, variant, frame_flags);
+
+    let mut answer = Vec::new();
+    write_dzip_header(&mut answer, &header);
+
+    let mut index = Vec::new();
+    for frame in data.chunks(frame_size) {
+        let mut compressed = bin_to_bytes(&deflate_encoding_with_dictionary(frame.to_vec(),
+                                                                            dictionary.unwrap_or_default(), variant));
+        if has_checksums {
+            let mut frame_header = Vec::new();
+            frame_header.extend((compressed.len() as u32).to_le_bytes());
+            frame_header.extend((frame.len() as u32).to_le_bytes());
+            frame_header.extend(crc32(frame).to_le_bytes());
+            compressed.splice(0..0, frame_header);
+        }
+
+        index.extend((compressed.len() as u32).to_le_bytes());
+        index.extend((frame.len() as u32).to_le_bytes());
+        answer.extend(compressed);
+    }
+
+    if is_seekable {
+        answer.extend(index);
+        answer.extend((data.len().div_ceil(frame_size) as u32).to_le_bytes());
+        answer.extend(INDEX_MAGIC);
+    }
+
+    answer
+}
+
+fn check_dictionary<'a>(header: &DzipHeader, dictionary: Option<&'a [u8]>) -> Result<&'a [u8], DecodeError> {
+    match header.dictionary_id {
+        Some(dictionary_id) => {
+            let dictionary = dictionary.ok_or(DecodeError::DictionaryRequired)?;
+            if adler32(dictionary) != dictionary_id {
+                return Err(DecodeError::DictionaryMismatch);
+            }
+            Ok(dictionary)
+        }
+        None => Ok(&[]),
+    }
+}
+
+/// Returns the size of the whole index given its last `INDEX_TRAILER_SIZE` bytes.
+fn index_size(trailer: &[u8]) -> Result<usize, DecodeError> {
+    if trailer[4..8] != INDEX_MAGIC {
+        return Err(DecodeError::InvalidHeader("frame index is missing"));
+    }
+    let number_frames = u32::from_le_bytes(trailer[0..4].try_into().unwrap()) as usize;
+
+    Ok(number_frames * INDEX_ENTRY_SIZE + INDEX_TRAILER_SIZE)
+}
+
+fn read_dzip_index(header: &DzipHeader, index: &[u8], file_size: u64) -> Result<Vec<DzipFrame>, DecodeError> {
+    let mut frames = Vec::new();
+    let mut compressed_offset = header.header_size() as u64;
+    let mut offset = 0;
+
+    for entry in index[..index.len() - INDEX_TRAILER_SIZE].chunks(INDEX_ENTRY_SIZE) {
+        let frame = DzipFrame {
+            compressed_offset,
+            compressed_size: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+            offset,
+            size: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+        };
+        compressed_offset += frame.compressed_size as u64;
+        offset += frame.size as u64;
+        frames.push(frame);
+    }
+
+    if compressed_offset + index.len() as u64 != file_size {
+        return Err(DecodeError::InvalidHeader("frame index does not match the file size"));
+    }
+    if offset != header.size {
+        return Err(DecodeError::SizeMismatch);
+    }
+
+    Ok(frames)
+}
+
+/// Reads the header of the frame at `compressed_offset` of a checksummed file that has no
+/// index. A header that does not fit into the file marks the frame as corrupted.
+fn read_frame_header(data: &[u8], compressed_offset: usize, offset: u64, number: usize)
+                     -> Result<DzipFrame, DecodeError> {
+    let frame_header = data.get(compressed_offset..compressed_offset + FRAME_HEADER_SIZE);
+    let size = frame_header.map_or(0, |x| u32::from_le_bytes(x[4..8].try_into().unwrap()));
+    let corrupted = DecodeError::CorruptedBlock { number, start: offset, end: offset + size as u64 };
+
+    let compressed_size = frame_header
+        .and_then(|x| u32::from_le_bytes(x[0..4].try_into().unwrap()).checked_add(FRAME_HEADER_SIZE as u32))
+        .filter(|x| compressed_offset + *x as usize <= data.len())
+        .ok_or(corrupted)?;
+
+    Ok(DzipFrame {
+        compressed_offset: compressed_offset as u64,
+        compressed_size,
+        offset,
+        size,
+    })
+}
+
+fn dzip_frame_decoding(compressed: &[u8], frame: &DzipFrame, dictionary: &[u8], header: &DzipHeader)
+                       -> Result<Vec<u8>, DecodeError> {
+    let (compressed, crc) = if header.has_checksums() {
+        let frame_header = compressed.get(..FRAME_HEADER_SIZE).ok_or(DecodeError::UnexpectedEnd)?;
+        if u32::from_le_bytes(frame_header[0..4].try_into().unwrap()) as usize != compressed.len() - FRAME_HEADER_SIZE ||
+            u32::from_le_bytes(frame_header[4..8].try_into().unwrap()) != frame.size {
+            return Err(DecodeError::SizeMismatch);
+        }
+        (&compressed[FRAME_HEADER_SIZE..], Some(u32::from_le_bytes(frame_header[8..12].try_into().unwrap())))
+    } else {
+        (compressed, None)
+    };
+
+    let (answer, compressed_size) = deflate_stream_decoding_with_dictionary(compressed, dictionary, header.variant())?;
+    if compressed_size != compressed.len() || answer.len() != frame.size as usize {
+        return Err(DecodeError::SizeMismatch);
+    }
+    if crc.is_some_and(|x| x != crc32(answer.as_slice())) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok(answer)
+}
+
+fn corrupted_block(number: usize, frame: &DzipFrame) -> DecodeError {
+    DecodeError::CorruptedBlock {
+        number,
+        start: frame.offset,
+        end: frame.offset + frame.size as u64,
+    }
+}
+
+/// Random access to a seekable file: only the frames overlapping the requested range are
+/// read and decoded.
+pub struct SeekableDzipReader<R: Read + Seek> {
+    reader: R,
+    header: DzipHeader,
+    frames: Vec<DzipFrame>,
+    dictionary: Vec<u8>,
+}
+
+impl<R: Read + Seek> SeekableDzipReader<R> {
+    /// Reads the header and the frame index. The dictionary must be the one the file was
+    /// compressed with, if any.
+    pub fn new(mut reader: R, dictionary: Option<&[u8]>) -> Result<SeekableDzipReader<R>, DecodeError> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+
+        let mut header_data = Vec::new();
+        reader.seek(SeekFrom::Start(0))?;
+        reader.by_ref().take((DZIP_HEADER_SIZE + DICTIONARY_ID_SIZE) as u64).read_to_end(&mut header_data)?;
+        let header = read_dzip_header(header_data.as_slice())?;
+        if !header.is_seekable() {
+            return Err(DecodeError::InvalidHeader("file is not seekable"));
+        }
+        let dictionary = check_dictionary(&header, dictionary)?.to_vec();
+
+        if file_size < (header.header_size() + INDEX_TRAILER_SIZE) as u64 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let mut trailer = [0; INDEX_TRAILER_SIZE];
+        reader.seek(SeekFrom::End(-(INDEX_TRAILER_SIZE as i64)))?;
+        reader.read_exact(&mut trailer)?;
+
+        let index_size = index_size(&trailer)? as u64;
+        if index_size > file_size - header.header_size() as u64 {
+            return Err(DecodeError::InvalidHeader("frame index does not match the file size"));
+        }
+        let mut index = vec![0; index_size as usize];
+        reader.seek(SeekFrom::Start(file_size - index_size))?;
+        reader.read_exact(&mut index)?;
+        let frames = read_dzip_index(&header, index.as_slice(), file_size)?;
+
+        Ok(SeekableDzipReader { reader, header, frames, dictionary })
+    }
+
+    /// Reads up to `len` bytes of the decompressed data starting at `offset`, the result is
+    /// shorter only at the end of the data.
+    pub fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>, DecodeError> {
+        let end = self.header.size.min(offset.saturating_add(len as u64));
+        let mut answer = Vec::new();
+        if offset >= end {
+            return Ok(answer);
+        }
+
+        let first_frame = self.frames.partition_point(|x| x.offset + x.size as u64 <= offset);
+        for (number, frame) in self.frames.iter().enumerate().skip(first_frame) {
+            if frame.offset >= end {
+                break;
+            }
+
+            let mut compressed = vec![0; frame.compressed_size as usize];
+            self.reader.seek(SeekFrom::Start(frame.compressed_offset))?;
+            self.reader.read_exact(&mut compressed)?;
+            let decoded = dzip_frame_decoding(compressed.as_slice(), frame, self.dictionary.as_slice(), &self.header)
+                .map_err(|_| corrupted_block(number, frame))?;
+
+            let start = offset.saturating_sub(frame.offset) as usize;
+            let stop = (end - frame.offset).min(frame.size as u64) as usize;
+            answer.extend_from_slice(&decoded[start..stop]);
+        }
+
+        Ok(answer)
+    }
+}
+
+/// Headerless files are either written by the first versions of dzip, in their own bit
+/// layout, or bare deflate streams such as the output of `DzipEncoder`, which are decoded
+/// with `legacy_variant`.
+pub fn dzip_decoding(data: Vec<u8>, dictionary: Option<&[u8]>, legacy_variant: DeflateVariant)
+                     -> Result<Vec<u8>, DecodeError> {
+    if !is_dzip(data.as_slice()) {
+        return legacy_decoding(data.as_slice()).or_else(|_| deflate_decoding(data, legacy_variant));
+    }
+
+    let header = read_dzip_header(data.as_slice())?;
+    let dictionary = check_dictionary(&header, dictionary)?;
+    let frames = if header.is_seekable() {
+        if data.len() < header.header_size() + INDEX_TRAILER_SIZE {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let index_size = index_size(&data[data.len() - INDEX_TRAILER_SIZE..])?;
+        if index_size > data.len() - header.header_size() {
+            return Err(DecodeError::InvalidHeader("frame index does not match the file size"));
+        }
+        Some(read_dzip_index(&header, &data[data.len() - index_size..], data.len() as u64)?)
+    } else {
+        None
+    };
+
+    let answer = if let Some(frames) = frames {
+        let mut answer = Vec::new();
+        for (number, frame) in frames.iter().enumerate() {
+            let start = frame.compressed_offset as usize;
+            let compressed = &data[start..start + frame.compressed_size as usize];
+            answer.extend(dzip_frame_decoding(compressed, frame, dictionary, &header)
+                .map_err(|_| corrupted_block(number, frame))?);
+        }
+        answer
+    } else if header.has_checksums() {
+        // every frame is decoded before the next header is read, so a broken size field is
+        // reported at its own frame, and a missing frame is reported like a broken one
+        let mut answer = Vec::new();
+        let mut compressed_offset = header.header_size();
+        let mut number = 0;
+        while (answer.len() as u64) < header.size {
+            let frame = read_frame_header(data.as_slice(), compressed_offset, answer.len() as u64, number)?;
+            let compressed = &data[compressed_offset..compressed_offset + frame.compressed_size as usize];
+            answer.extend(dzip_frame_decoding(compressed, &frame, dictionary, &header)
+                .map_err(|_| corrupted_block(number, &frame))?);
+            compressed_offset += frame.compressed_size as usize;
+            number += 1;
+        }
+        answer
+    } else {
+        deflate_stream_decoding_with_dictionary(&data[header.header_size()..], dictionary, header.variant())?.0
+    };
+
+    if answer.len() as u64 != header.size {
+        return Err(DecodeError::SizeMismatch);
+    }
+    if crc32(answer.as_slice()) != header.crc {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_header_offsets(data: &[u8]) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut offset = DZIP_HEADER_SIZE;
+        while offset < data.len() {
+            offsets.push(offset);
+            offset += FRAME_HEADER_SIZE + u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        }
+        offsets
+    }
+
+    #[test]
+    fn broken_frame_header_is_located() {
+        let data = b"frames of a checksummed file ".repeat(100);
+        let compressed = dzip_framed_encoding(data, None, DeflateVariant::Deflate, 1000, false, true);
+        let offsets = frame_header_offsets(&compressed);
+        assert_eq!(offsets.len(), 3);
+
+        for (field, value) in [(0, u32::MAX), (0, 1), (4, 1001)] {
+            let mut broken = compressed.clone();
+            broken[offsets[1] + field..offsets[1] + field + 4].copy_from_slice(&value.to_le_bytes());
+            assert!(matches!(dzip_decoding(broken, None, DeflateVariant::Deflate),
+                             Err(DecodeError::CorruptedBlock { number: 1, start: 1000, .. })));
+        }
+    }
+
+    #[test]
+    fn dictionary_must_match() {
+        let dictionary = b"common words of the messages".as_slice();
+        let data = b"the messages share common words".to_vec();
+        let compressed = dzip_encoding(data.clone(), Some(dictionary), DeflateVariant::Deflate);
+
+        assert_eq!(dzip_decoding(compressed.clone(), Some(dictionary), DeflateVariant::Deflate).unwrap(), data);
+        assert_eq!(dzip_decoding(compressed.clone(), None, DeflateVariant::Deflate), Err(DecodeError::DictionaryRequired));
+        assert_eq!(dzip_decoding(compressed, Some(b"other words"), DeflateVariant::Deflate),
+                   Err(DecodeError::DictionaryMismatch));
+    }
+
+    #[test]
+    fn ranges_are_read_from_the_frames() {
+        let data = (0..2900).map(|x| (x % 251) as u8).collect::<Vec<_>>();
+        let compressed = dzip_framed_encoding(data.clone(), None, DeflateVariant::Deflate, 1000, true, false);
+        let mut reader = SeekableDzipReader::new(std::io::Cursor::new(compressed), None).unwrap();
+
+        // inside one frame, across frame boundaries, ending at the end of the data
+        for (offset, len) in [(10, 20), (1000, 1000), (990, 20), (500, 2000), (0, 2900), (2899, 1), (2000, 900)] {
+            assert_eq!(reader.read_at(offset, len).unwrap(), &data[offset as usize..offset as usize + len],
+                       "{} bytes at {}", len, offset);
+        }
+        // past the end of the data the result is shorter
+        assert_eq!(reader.read_at(2800, 500).unwrap(), &data[2800..]);
+        assert_eq!(reader.read_at(0, usize::MAX).unwrap(), data);
+        assert!(reader.read_at(2900, 10).unwrap().is_empty());
+        assert!(reader.read_at(u64::MAX, 10).unwrap().is_empty());
+        assert!(reader.read_at(1500, 0).unwrap().is_empty());
+    }
+}
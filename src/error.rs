@@ -0,0 +1,81 @@
+use std::fmt::{Display, Formatter};
+
+/// The reason compressed data could not be decoded. New variants may be added in minor releases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The data ends in the middle of a stream.
+    UnexpectedEnd,
+    /// A deflate block has the reserved type 3.
+    UnsupportedBlockType(u8),
+    /// The bits do not form a code of the current Huffman table.
+    InvalidHuffmanCode,
+    /// The code lengths of a dynamic block do not describe a prefix code.
+    InvalidCodeLengths,
+    /// The length of a stored block does not match its complement.
+    InvalidBlockLength,
+    /// A literal/length or distance symbol that the format does not define.
+    InvalidSymbol(i32),
+    /// A back reference points before the start of the data.
+    InvalidDistance(usize),
+    /// A container header is malformed, the text says why.
+    InvalidHeader(&'static str),
+    /// A zip entry is compressed with a method other than stored, deflate or Deflate64.
+    UnsupportedMethod(u16),
+    /// A `.dzip` file written by a newer format version.
+    UnsupportedVersion(u8),
+    /// The checksum stored with the data does not match the decoded data.
+    ChecksumMismatch,
+    /// The size stored with the data does not match the decoded data.
+    SizeMismatch,
+    /// The stream was compressed with a preset dictionary and none was given.
+    DictionaryRequired,
+    /// The given preset dictionary is not the one the stream was compressed with.
+    DictionaryMismatch,
+    /// Reading the compressed data failed.
+    Io(std::io::ErrorKind),
+    /// A frame of a framed `.dzip` file can not be decoded.
+    CorruptedBlock {
+        /// The number of the frame, from 0.
+        number: usize,
+        /// The offset of the first decompressed byte of the frame.
+        start: u64,
+        /// The offset after the last decompressed byte of the frame.
+        end: u64,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of compressed data"),
+            DecodeError::UnsupportedBlockType(t) => write!(f, "block type {} is not supported", t),
+            DecodeError::InvalidHuffmanCode => write!(f, "invalid huffman code"),
+            DecodeError::InvalidCodeLengths => write!(f, "invalid code lengths"),
+            DecodeError::InvalidBlockLength => write!(f, "stored block length does not match its complement"),
+            DecodeError::InvalidSymbol(s) => write!(f, "invalid symbol {}", s),
+            DecodeError::InvalidDistance(d) => write!(f, "distance {} is too far back", d),
+            DecodeError::InvalidHeader(reason) => write!(f, "invalid header: {}", reason),
+            DecodeError::UnsupportedMethod(m) => write!(f, "compression method {} is not supported", m),
+            DecodeError::UnsupportedVersion(v) => write!(f, "dzip format version {} is not supported", v),
+            DecodeError::ChecksumMismatch => write!(f, "checksum of the decompressed data does not match"),
+            DecodeError::SizeMismatch => write!(f, "size of the decompressed data does not match"),
+            DecodeError::DictionaryRequired => write!(f, "a preset dictionary is required"),
+            DecodeError::DictionaryMismatch => write!(f, "preset dictionary does not match"),
+            DecodeError::Io(kind) => write!(f, "i/o error: {}", kind),
+            DecodeError::CorruptedBlock { number, start, end } =>
+                write!(f, "block {} with the decompressed bytes {}..{} is corrupted", number, start, end),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => DecodeError::UnexpectedEnd,
+            kind => DecodeError::Io(kind),
+        }
+    }
+}
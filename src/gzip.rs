@@ -0,0 +1,310 @@
+//! The gzip format (RFC 1952): members with a header, a deflate stream, the CRC-32 and
+//! the size of the data.
+
+use crate::bgzf::has_bgzf_block_size;
+use crate::bin_num::bin_to_bytes;
+use crate::crc32::crc32;
+use crate::error::DecodeError;
+use crate::{deflate_encoding_with_level, deflate_stream_decoding, CompressionLevel, DeflateVariant};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_HEADER_SIZE: usize = 10;
+const GZIP_TRAILER_SIZE: usize = 8;
+const DEFLATE_METHOD: u8 = 8;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// The operating system field of files written on Unix.
+pub const OS_UNIX: u8 = 3;
+
+/// The fields of a member header. The defaults describe data without a name or time.
+#[derive(Debug, Clone, Default)]
+pub struct GzipHeader {
+    /// The modification time in seconds since the Unix epoch, 0 if unknown.
+    pub mtime: u32,
+    /// The raw extra field, a sequence of subfields.
+    pub extra: Option<Vec<u8>>,
+    /// The original file name, without the terminating zero.
+    pub name: Option<Vec<u8>>,
+    /// A comment, without the terminating zero.
+    pub comment: Option<Vec<u8>>,
+    /// The operating system the file was written on, such as [`OS_UNIX`].
+    pub os: u8,
+}
+
+/// Returns true if the data starts with the gzip magic number.
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+}
+
+fn read_u16(data: &[u8], index: usize) -> Result<u16, DecodeError> {
+    let bytes = data.get(index..index + 2).ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], index: usize) -> Result<u32, DecodeError> {
+    let bytes = data.get(index..index + 4).ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_zero_terminated(data: &[u8], index: &mut usize) -> Result<Vec<u8>, DecodeError> {
+    let length = data.get(*index..)
+        .and_then(|x| x.iter().position(|x| *x == 0))
+        .ok_or(DecodeError::UnexpectedEnd)?;
+    let value = data[*index..*index + length].to_vec();
+    *index += length + 1;
+
+    Ok(value)
+}
+
+fn write_gzip_header(answer: &mut Vec<u8>, header: &GzipHeader) {
+    let mut flags = 0;
+    if header.extra.is_some() {
+        flags |= FLAG_EXTRA;
+    }
+    if header.name.is_some() {
+        flags |= FLAG_NAME;
+    }
+    if header.comment.is_some() {
+        flags |= FLAG_COMMENT;
+    }
+
+    answer.extend(GZIP_MAGIC);
+    answer.push(DEFLATE_METHOD);
+    answer.push(flags);
+    answer.extend(header.mtime.to_le_bytes());
+    answer.push(0);
+    answer.push(header.os);
+
+    if let Some(extra) = &header.extra {
+        answer.extend((extra.len() as u16).to_le_bytes());
+        answer.extend(extra);
+    }
+    for field in [&header.name, &header.comment].into_iter().flatten() {
+        answer.extend(field);
+        answer.push(0);
+    }
+}
+
+pub(crate) fn read_gzip_header(data: &[u8]) -> Result<(GzipHeader, usize), DecodeError> {
+    if data.len() < GZIP_HEADER_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if !is_gzip(data) {
+        return Err(DecodeError::InvalidHeader("not a gzip member"));
+    }
+    if data[2] != DEFLATE_METHOD {
+        return Err(DecodeError::InvalidHeader("unknown compression method"));
+    }
+
+    let flags = data[3];
+    let mut header = GzipHeader {
+        mtime: read_u32(data, 4)?,
+        extra: None,
+        name: None,
+        comment: None,
+        os: data[9],
+    };
+
+    let mut index = GZIP_HEADER_SIZE;
+    if flags & FLAG_EXTRA != 0 {
+        let length = read_u16(data, index)? as usize;
+        header.extra = Some(data.get(index + 2..index + 2 + length).ok_or(DecodeError::UnexpectedEnd)?.to_vec());
+        index += 2 + length;
+    }
+    if flags & FLAG_NAME != 0 {
+        header.name = Some(read_zero_terminated(data, &mut index)?);
+    }
+    if flags & FLAG_COMMENT != 0 {
+        header.comment = Some(read_zero_terminated(data, &mut index)?);
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        let header_crc = read_u16(data, index)?;
+        if header_crc != crc32(&data[..index]) as u16 {
+            return Err(DecodeError::InvalidHeader("header checksum does not match"));
+        }
+        index += 2;
+    }
+    if index > data.len() {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    Ok((header, index))
+}
+
+/// Compresses the data into one member with the default compression level.
+pub fn gzip_encoding(data: Vec<u8>, header: &GzipHeader) -> Vec<u8> {
+    gzip_encoding_with_level(data, header, CompressionLevel::Default)
+}
+
+/// Compresses the data into one member.
+pub fn gzip_encoding_with_level(data: Vec<u8>, header: &GzipHeader, level: CompressionLevel) -> Vec<u8> {
+    let crc = crc32(data.as_slice());
+    let size = data.len() as u32;
+    let compressed = bin_to_bytes(&deflate_encoding_with_level(data, &[], level, DeflateVariant::Deflate));
+
+    let mut answer = Vec::new();
+    write_gzip_member(&mut answer, header, compressed.as_slice(), crc, size);
+
+    answer
+}
+
+/// Writes a member around already compressed data, `crc` and `size` describe the
+/// uncompressed data.
+pub(crate) fn write_gzip_member(answer: &mut Vec<u8>, header: &GzipHeader, compressed: &[u8], crc: u32, size: u32) {
+    write_gzip_header(answer, header);
+    answer.extend(compressed);
+    answer.extend(crc.to_le_bytes());
+    answer.extend(size.to_le_bytes());
+}
+
+pub(crate) fn gzip_member_decoding(data: &[u8]) -> Result<(GzipHeader, Vec<u8>, usize), DecodeError> {
+    let (header, header_size) = read_gzip_header(data)?;
+    let (answer, deflate_size) = deflate_stream_decoding(&data[header_size..], DeflateVariant::Deflate)?;
+
+    let trailer_start = header_size + deflate_size;
+    if data.len() < trailer_start + GZIP_TRAILER_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if read_u32(data, trailer_start)? != crc32(answer.as_slice()) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+    if read_u32(data, trailer_start + 4)? != answer.len() as u32 {
+        return Err(DecodeError::SizeMismatch);
+    }
+
+    Ok((header, answer, trailer_start + GZIP_TRAILER_SIZE))
+}
+
+/// Decodes every member and returns their data one after another, as `gzip -d` does with
+/// concatenated files. The bytes after the last member are ignored.
+pub fn gzip_decoding(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    Ok(gzip_decoding_with_trailing_size(data)?.0)
+}
+
+/// Like [`gzip_decoding`], also returns the number of bytes after the last member, which
+/// `gzip -d` ignores with the warning "trailing garbage ignored". Zero padding, as written
+/// by tape archivers, is not counted. Bytes that start like a member must be a valid one.
+pub fn gzip_decoding_with_trailing_size(data: &[u8]) -> Result<(Vec<u8>, usize), DecodeError> {
+    let mut answer = Vec::new();
+
+    let mut start_member = 0;
+    loop {
+        let (_, member, member_size) = gzip_member_decoding(&data[start_member..])?;
+        answer.extend(member);
+        start_member += member_size;
+
+        if !is_gzip(&data[start_member..]) {
+            break;
+        }
+    }
+
+    Ok((answer, trailing_size(&data[start_member..])))
+}
+
+fn trailing_size(data: &[u8]) -> usize {
+    if data.iter().all(|x| *x == 0) {
+        0
+    } else {
+        data.len()
+    }
+}
+
+/// Every member is compressed again with the best compression level and keeps its header
+/// except for the extra field.
+/// The result is decoded once more to make sure it gives back the same data.
+///
+/// BGZF blocks are refused: merged or resized blocks would break the indexes that point
+/// into the file by block offset.
+pub fn gzip_recompression(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut answer = Vec::new();
+    let mut original = Vec::new();
+
+    let mut start_member = 0;
+    loop {
+        let (mut header, member, member_size) = gzip_member_decoding(&data[start_member..])?;
+        if header.extra.as_deref().is_some_and(has_bgzf_block_size) {
+            return Err(DecodeError::InvalidHeader("BGZF blocks can not be recompressed"));
+        }
+        // other subfields may describe the compressed data too and would become stale
+        header.extra = None;
+        original.extend_from_slice(member.as_slice());
+        answer.extend(gzip_encoding_with_level(member, &header, CompressionLevel::Best));
+        start_member += member_size;
+
+        if !is_gzip(&data[start_member..]) {
+            break;
+        }
+    }
+    // the zero padding is dropped, other trailing bytes are kept as they are
+    if trailing_size(&data[start_member..]) != 0 {
+        answer.extend_from_slice(&data[start_member..]);
+    }
+
+    if gzip_decoding(answer.as_slice())? != original {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_trailer_is_rejected() {
+        let data = b"the trailer holds the CRC-32 and the size of the data".to_vec();
+        let compressed = gzip_encoding(data.clone(), &GzipHeader::default());
+        assert_eq!(gzip_decoding(&compressed).unwrap(), data);
+
+        let trailer_start = compressed.len() - GZIP_TRAILER_SIZE;
+        let mut broken = compressed.clone();
+        broken[trailer_start] ^= 1;
+        assert_eq!(gzip_decoding(&broken), Err(DecodeError::ChecksumMismatch));
+
+        let mut broken = compressed.clone();
+        broken[trailer_start + 4] ^= 1;
+        assert_eq!(gzip_decoding(&broken), Err(DecodeError::SizeMismatch));
+
+        assert_eq!(gzip_decoding(&compressed[..compressed.len() - 1]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn concatenated_members_are_decoded() {
+        let (first, second) = (b"the first member, ".to_vec(), b"the second member".to_vec());
+        let mut compressed = gzip_encoding(first.clone(), &GzipHeader::default());
+        compressed.extend(gzip_encoding(Vec::new(), &GzipHeader::default()));
+        compressed.extend(gzip_encoding(second.clone(), &GzipHeader { name: Some(b"b".to_vec()), ..Default::default() }));
+        let data = [first, second].concat();
+        assert_eq!(gzip_decoding(&compressed).unwrap(), data);
+
+        // tape archives pad the file with zeros
+        compressed.extend([0; 512]);
+        assert_eq!(gzip_decoding(&compressed).unwrap(), data);
+        assert_eq!(crate::decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn trailing_garbage_is_ignored() {
+        let data = b"the members are kept".to_vec();
+        let mut compressed = gzip_encoding(data.clone(), &GzipHeader::default());
+        compressed.extend([0; 16]);
+        assert_eq!(gzip_decoding_with_trailing_size(&compressed).unwrap(), (data.clone(), 0));
+
+        compressed.extend(b"garbage");
+        assert_eq!(gzip_decoding_with_trailing_size(&compressed).unwrap(), (data.clone(), 23));
+        let recompressed = gzip_recompression(&compressed).unwrap();
+        assert!(recompressed.ends_with(b"garbage"));
+        assert_eq!(gzip_decoding(&recompressed).unwrap(), data);
+
+        // a member that is cut off is still an error
+        let mut truncated = gzip_encoding(data.clone(), &GzipHeader::default());
+        let member = truncated.clone();
+        truncated.extend(&member[..member.len() - 1]);
+        assert_eq!(gzip_decoding(&truncated), Err(DecodeError::UnexpectedEnd));
+    }
+}
@@ -2,6 +2,8 @@ use std::cmp::max;
 use std::collections::HashMap;
 use std::hash::Hash;
 
+const MAX_CODE_LENGTH: u32 = 15;
+
 pub fn huffman_lengths_to_bin_code<T: Clone + Eq + Hash + Ord>(lengths: Vec<(T, i32)>) ->
                                                         (HashMap<T, Vec<bool>>, HashMap<Vec<bool>, T>) {
 
@@ -40,16 +42,35 @@ pub fn huffman_lengths_to_bin_code<T: Clone + Eq + Hash + Ord>(lengths: Vec<(T,
     (answer, reverse_answer)
 }
 
+/// Over-subscribed lengths do not describe a prefix code, the decoder would assign the same
+/// code to several symbols.
+pub fn is_prefix_code_lengths(lengths: &[i32]) -> bool {
+    lengths.iter().filter(|x| **x > 0).map(|x| 1u32 << (MAX_CODE_LENGTH - *x as u32)).sum::<u32>() <=
+        1 << MAX_CODE_LENGTH
+}
+
+pub fn lengths_to_huffman_codes(lengths: &[i32]) -> (HashMap<i32, Vec<bool>>, HashMap<Vec<bool>, i32>) {
+    let mut symbol_lengths = lengths
+        .iter()
+        .enumerate()
+        .filter(|x| *x.1 != 0)
+        .map(|x| (x.0 as i32, *x.1))
+        .collect::<Vec<(i32, i32)>>();
+    symbol_lengths.sort_by_key(|x| (x.1, x.0));
+
+    huffman_lengths_to_bin_code(symbol_lengths)
+}
+
 fn merge_coins(c1: &(i32, HashMap<usize, i32>), c2: &(i32, HashMap<usize, i32>)) -> (i32, HashMap<usize, i32>){
     let w = c1.0 + c2.0;
 
     let mut d = c1.1.clone();
     for (k, v) in &c2.1 {
         d.entry(*k).or_insert(0);
-        d.insert(*k, max(*d.get(&k).unwrap(), *v));
+        d.insert(*k, max(*d.get(k).unwrap(), *v));
     }
 
-    return (w, d);
+    (w, d)
 }
 
 pub fn bounded_huffman(max_len: i32, number_repetitions: &[i32]) -> Vec<i32>{
@@ -72,20 +93,20 @@ pub fn bounded_huffman(max_len: i32, number_repetitions: &[i32]) -> Vec<i32>{
         coins.clear();
         coins = prev_coins;
         coins.extend(new_coins);
-        coins.sort_by(|f, s| f.0.cmp(&s.0));
+        coins.sort_by_key(|f| f.0);
      }
 
     let mut res = vec![0; number_repetitions.len()];
 
-    for i in 0..max(number_repetitions.len(), number_repetitions.len() * 2 - 2) as usize {
-        for (k, v) in &coins[i].1 {
+    for coin in coins.iter().take(max(number_repetitions.len(), number_repetitions.len() * 2 - 2)) {
+        for (k, v) in &coin.1 {
             if res[*k] < *v {
                 res[*k] = *v;
             }
         }
     }
 
-    return res;
+    res
 }
 
 
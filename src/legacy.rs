@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use crate::bin_num::bin_to_num;
+use crate::error::DecodeError;
+use crate::huffman::{is_prefix_code_lengths, lengths_to_huffman_codes};
+use crate::DeflateElementType::{EndBlock, JustElement};
+use crate::{get_deflate_copy_length, to_deflate_element_type, DeflateOffset, DeflateVariant,
+            MAX_HUFFMAN_CODE_LENGTH, SEQUENCE_LENGTH_COMMAND};
+
+// the 3-bit count of the bits in the last byte
+const LAST_BYTE_SIZE_BITS: usize = 3;
+// the first versions wrote only dynamic blocks, with the block type bits in this order
+const DYNAMIC_BLOCK_TYPE: i32 = 0b01;
+// an empty file has no blocks, only the count, which is right-aligned like every last byte
+const EMPTY_FILE: [u8; 1] = [0b011];
+
+/// The bytes are packed MSB-first, the first 3 bits are the number of bits used in the last
+/// byte (0 meaning all 8), which holds them right-aligned.
+fn legacy_bits(data: &[u8]) -> Result<Vec<bool>, DecodeError> {
+    let Some(&first) = data.first() else {
+        return Err(DecodeError::UnexpectedEnd);
+    };
+    let last_byte_size = match first >> (8 - LAST_BYTE_SIZE_BITS) {
+        0 => 8,
+        v => v as usize,
+    };
+
+    let mut answer = data.iter().flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0)).collect::<Vec<bool>>();
+    let last_byte_start = answer.len() - 8;
+    answer.drain(last_byte_start..last_byte_start + 8 - last_byte_size);
+    Ok(answer)
+}
+
+type HuffmanDecodingTable = HashMap<Vec<bool>, i32>;
+
+fn read_huffman_symbol(data: &[bool], read_index: &mut usize, lang: &HuffmanDecodingTable) -> Result<i32, DecodeError> {
+    let mut temp = Vec::new();
+    while temp.len() < MAX_HUFFMAN_CODE_LENGTH {
+        if *read_index >= data.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        temp.push(data[*read_index]);
+        *read_index += 1;
+
+        if let Some(symbol) = lang.get(&temp) {
+            return Ok(*symbol);
+        }
+    }
+
+    Err(DecodeError::InvalidHuffmanCode)
+}
+
+/// Numbers are written MSB-first, unlike in RFC 1951.
+fn read_number(data: &[bool], read_index: &mut usize, count: usize) -> Result<i32, DecodeError> {
+    if *read_index + count > data.len() {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let value = bin_to_num(&data[*read_index..*read_index + count]);
+    *read_index += count;
+
+    Ok(value)
+}
+
+/// The literal and the distance code lengths are two separate sequences, a repetition of the
+/// previous length repeats the last one that is not zero.
+fn read_code_lengths(data: &[bool], read_index: &mut usize, lang_len_elements: &HuffmanDecodingTable,
+                     count: usize) -> Result<Vec<i32>, DecodeError> {
+    let mut lengths: Vec<i32> = Vec::with_capacity(count);
+
+    while lengths.len() < count {
+        let (length, repetitions) = match read_huffman_symbol(data, read_index, lang_len_elements)? {
+            v @ 0..=15 => (v, 1),
+            16 => {
+                let previous = *lengths.iter().rev().find(|x| **x != 0).ok_or(DecodeError::InvalidCodeLengths)?;
+                (previous, read_number(data, read_index, 2)? + 3)
+            }
+            17 => (0, read_number(data, read_index, 3)? + 3),
+            18 => (0, read_number(data, read_index, 7)? + 11),
+            v => return Err(DecodeError::InvalidSymbol(v))
+        };
+
+        if lengths.len() + repetitions as usize > count {
+            return Err(DecodeError::InvalidCodeLengths);
+        }
+        lengths.extend(std::iter::repeat_n(length, repetitions as usize));
+    }
+
+    Ok(lengths)
+}
+
+fn huffman_codes(lengths: &[i32]) -> Result<HuffmanDecodingTable, DecodeError> {
+    if !is_prefix_code_lengths(lengths) {
+        return Err(DecodeError::InvalidCodeLengths);
+    }
+    Ok(lengths_to_huffman_codes(lengths).1)
+}
+
+/// Returns true at the last block.
+fn legacy_block_decoding(answer: &mut Vec<u8>, data: &[bool], read_index: &mut usize) -> Result<bool, DecodeError> {
+    let is_end_block = read_number(data, read_index, 1)? == 1;
+    let block_type = read_number(data, read_index, 2)?;
+    if block_type != DYNAMIC_BLOCK_TYPE {
+        return Err(DecodeError::UnsupportedBlockType(block_type as u8));
+    }
+
+    let hlit = read_number(data, read_index, 5)? as usize + 257;
+    // the number of distance codes, without the offset of 1 that RFC 1951 has
+    let hdist = read_number(data, read_index, 5)? as usize;
+    let hclen = read_number(data, read_index, 4)? as usize + 4;
+
+    let mut deflate_len_lengths = vec![0; SEQUENCE_LENGTH_COMMAND.len()];
+    for length_index in &SEQUENCE_LENGTH_COMMAND[..hclen] {
+        deflate_len_lengths[*length_index as usize] = read_number(data, read_index, 3)?;
+    }
+    let lang_len_elements = huffman_codes(&deflate_len_lengths)?;
+
+    let deflate_lengths = read_code_lengths(data, read_index, &lang_len_elements, hlit)?;
+    let offset_lengths = read_code_lengths(data, read_index, &lang_len_elements, hdist)?;
+    let lang_deflate_elements = huffman_codes(&deflate_lengths)?;
+    let lang_deflate_offset_elements = huffman_codes(&offset_lengths)?;
+
+    let variant = DeflateVariant::Deflate;
+    loop {
+        let symbol = read_huffman_symbol(data, read_index, &lang_deflate_elements)?;
+
+        match to_deflate_element_type(symbol, variant)? {
+            JustElement(v) => answer.push(v),
+            EndBlock => break,
+            elem => {
+                let number_additional_bits = elem.get_number_additional_bits() as usize;
+                let length_match = get_deflate_copy_length(&elem) as usize +
+                    read_number(data, read_index, number_additional_bits)? as usize;
+
+                let offset_symbol = read_huffman_symbol(data, read_index, &lang_deflate_offset_elements)?;
+                if offset_symbol >= variant.number_offset_codes() {
+                    return Err(DecodeError::InvalidSymbol(offset_symbol));
+                }
+                let offset_elem = DeflateOffset {
+                    main_value: offset_symbol as u8,
+                    additional_bits: 0,
+                };
+
+                let number_additional_bits = offset_elem.get_number_additional_bits() as usize;
+                let offset = offset_elem.get_base_offset() as usize +
+                    read_number(data, read_index, number_additional_bits)? as usize;
+
+                if offset > answer.len() {
+                    return Err(DecodeError::InvalidDistance(offset));
+                }
+                for _ in 0..length_match {
+                    answer.push(answer[answer.len() - offset]);
+                }
+            }
+        }
+    }
+
+    Ok(is_end_block)
+}
+
+/// Decodes the headerless files written by the first versions of dzip. Their blocks are
+/// dynamic deflate blocks in a bit layout of their own, so other decoders can not read them.
+/// The stream must end exactly at the last bit.
+pub fn legacy_decoding(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data == EMPTY_FILE {
+        return Ok(Vec::new());
+    }
+
+    let bin_data = legacy_bits(data)?;
+    let mut read_index = LAST_BYTE_SIZE_BITS;
+    let mut answer = Vec::new();
+    while !legacy_block_decoding(&mut answer, &bin_data, &mut read_index)? {}
+
+    if read_index != bin_data.len() {
+        return Err(DecodeError::InvalidHeader("data after the end of the stream"));
+    }
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // written by the first version of dzip
+    const REPEATED: [u8; 18] = [
+        0x15, 0x83, 0xe1, 0x12, 0x00, 0x00, 0x00, 0x0c, 0x21, 0x2a, 0xdb, 0x37, 0xf8, 0x3b, 0xfc, 0x43,
+        0xb7, 0x29,
+    ];
+
+    const TEXT: [u8; 60] = [
+        0xd5, 0x2b, 0xe0, 0xdb, 0x00, 0x20, 0x84, 0x18, 0x61, 0x71, 0x5f, 0xaf, 0x8b, 0x84, 0xb7, 0xb5,
+        0x38, 0x10, 0xa7, 0x10, 0xe8, 0x8c, 0x08, 0x1f, 0xd4, 0xef, 0x4c, 0x8d, 0x45, 0x3e, 0xab, 0xd3,
+        0x6a, 0x52, 0xa6, 0x4e, 0x82, 0x9b, 0x12, 0x7b, 0xe1, 0x11, 0xf5, 0xec, 0x4a, 0xb7, 0xf2, 0x08,
+        0xb4, 0x3a, 0xed, 0xa2, 0x72, 0xeb, 0x06, 0xfc, 0x6d, 0xaf, 0xf1, 0x3e,
+    ];
+
+    #[test]
+    fn files_of_the_first_version() {
+        assert_eq!(legacy_decoding(&REPEATED).unwrap(), b"abcabcabcabcabcabcabc");
+        assert_eq!(legacy_decoding(&TEXT).unwrap(), b"hello hello hello, the first dzip format packed bits MSB-first");
+        assert_eq!(legacy_decoding(&EMPTY_FILE).unwrap(), b"");
+    }
+
+    #[test]
+    fn headerless_files_decode() {
+        assert_eq!(crate::decompress(&TEXT).unwrap(), legacy_decoding(&TEXT).unwrap());
+        let bare = &crate::compress(b"hello hello hello")[crate::dzip_format::DZIP_HEADER_SIZE..];
+        assert_eq!(crate::decompress(bare).unwrap(), b"hello hello hello");
+    }
+
+    #[test]
+    fn truncated_file() {
+        assert!(legacy_decoding(&TEXT[..TEXT.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,813 @@
+//! Deflate compression with the `.dzip` container, the library behind the `dzip` tool.
+//!
+//! [`compress`] and [`decompress`] handle whole buffers, [`DzipEncoder`], [`DzipDecoder`] and
+//! [`Inflater`] handle streams. The modules hold the other formats the tool reads and writes,
+//! such as gzip, zlib and zip archives. Every public item follows semantic versioning.
+//!
+//! ```
+//! let compressed = dzip::compress(b"hello hello hello");
+//! assert_eq!(dzip::decompress(&compressed).unwrap(), b"hello hello hello");
+//! ```
+
+#![warn(missing_docs)]
+
+use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::fmt::{Debug};
+
+use crate::bin_num::{bin_to_bytes, bytes_to_bin, to_const_size_lsb_bin};
+use crate::compared_element::ComparedElement;
+use crate::DeflateElementType::{EndBlock, JustElement, LengthMatch, LengthMatchWithAdd,
+                                LengthMatchWithBinAdd, LengthMatchWithFifthAdd, LengthMatchWithFourthAdd,
+                                LengthMatchWithThirdAdd, LongMatchLength, MaxMatchLength};
+use crate::dzip_format::{dzip_decoding, dzip_encoding};
+use crate::gzip::{gzip_decoding, is_gzip};
+use crate::huffman::{bounded_huffman, lengths_to_huffman_codes};
+use crate::lz77::{encoding_lz77, Lz77Element};
+use crate::lzw::{is_lzw, lzw_decoding};
+use crate::lz77::Lz77Element::{ReferenceValue, SimpleValue};
+use crate::lz77::{MAX_COINCIDENCE_SIZE, MAX_DEFLATE64_COINCIDENCE_SIZE, MAX_DEFLATE64_SHIFT, MAX_SHIFT,
+                  MIN_COINCIDENCE_SIZE};
+use crate::zlib::{is_zlib, zlib_decoding, zlib_encoding};
+
+pub use crate::error::DecodeError;
+pub use crate::stream::{DzipDecoder, DzipEncoder, Inflater, Status};
+
+mod lz77;
+mod huffman;
+mod compared_element;
+mod bin_num;
+mod error;
+mod crc32;
+pub mod gzip;
+mod adler32;
+pub mod zlib;
+pub mod zip;
+pub mod dzip_format;
+pub mod png;
+pub mod bgzf;
+pub mod sfx;
+pub mod scan;
+pub mod lzw;
+mod crc24;
+pub mod armor;
+mod stream;
+mod legacy;
+#[cfg(test)]
+mod test_data;
+
+const SEQUENCE_LENGTH_COMMAND: [i32; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+const MAX_HUFFMAN_CODE_LENGTH: usize = 15;
+const NUMBER_OFFSET_CODES: i32 = 30;
+const NUMBER_DEFLATE64_OFFSET_CODES: i32 = 32;
+const MAX_STORED_BLOCK_SIZE: usize = 65535;
+const MAX_BLOCK_ELEMENTS: usize = 65535;
+/// The largest preset dictionary: back references reach only 32 KiB into it.
+pub const MAX_DICTIONARY_SIZE: usize = 32768;
+const DEFAULT_MAX_CHAIN_LENGTH: usize = 10;
+const BEST_MAX_CHAIN_LENGTH: usize = 1024;
+
+/// The flavour of the deflate stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeflateVariant {
+    /// RFC 1951: a 32 KiB window and matches of up to 258 bytes.
+    Deflate,
+    /// The zip method 9: a 64 KiB window, matches of up to 65538 bytes and distance codes 30 and 31.
+    Deflate64,
+}
+
+impl DeflateVariant {
+    fn max_shift(&self) -> u32 {
+        match self {
+            DeflateVariant::Deflate => MAX_SHIFT,
+            DeflateVariant::Deflate64 => MAX_DEFLATE64_SHIFT,
+        }
+    }
+
+    fn max_coincidence_size(&self) -> u32 {
+        match self {
+            DeflateVariant::Deflate => MAX_COINCIDENCE_SIZE,
+            DeflateVariant::Deflate64 => MAX_DEFLATE64_COINCIDENCE_SIZE,
+        }
+    }
+
+    fn number_offset_codes(&self) -> i32 {
+        match self {
+            DeflateVariant::Deflate => NUMBER_OFFSET_CODES,
+            DeflateVariant::Deflate64 => NUMBER_DEFLATE64_OFFSET_CODES,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DeflateOffset {
+    main_value: u8,
+    additional_bits: u16,
+}
+
+impl DeflateOffset {
+    fn new(offset: u32) -> DeflateOffset {
+        if offset <= 4 {
+            return DeflateOffset {
+                main_value: offset as u8 - 1,
+                additional_bits: 0,
+            };
+        }
+        let base_degree = (offset - 1).ilog2() as u8;
+        let base_num = u32::pow(2, base_degree as u32) + 1;
+
+        let delta;
+        let mid = base_num + (base_num >> 1) - 1;
+
+        let main_value = if offset <= mid {
+            delta = offset - base_num;
+            base_degree << 1
+        } else {
+            delta = offset - mid - 1;
+            (base_degree << 1) + 1
+        };
+
+        DeflateOffset {
+            main_value,
+            additional_bits: delta as u16,
+        }
+    }
+
+    fn get_base_offset(&self) -> u32 {
+        if self.main_value < 4 {
+            return (self.main_value + 1) as u32;
+        }
+
+        let base_num = u32::pow(2, (self.main_value >> 1) as u32) + 1;
+        if (self.main_value & 1) == 0 {
+            return base_num;
+        }
+
+        base_num + (base_num >> 1)
+    }
+
+    fn get_number_additional_bits(&self) -> u8 {
+        if self.main_value <= 3 {
+            return 0;
+        }
+
+        (self.main_value >> 1) - 1
+    }
+}
+
+
+#[derive(Clone)]
+enum DeflateLength {
+    SimpleLength(u8),
+    RetryPrevious(u8),
+    RetryZero(u8),
+    BigRetryZero(u8),
+}
+
+#[derive(Clone)]
+enum DeflateElementType {
+    JustElement(u8),
+    EndBlock,
+    LengthMatch(u16),
+    LengthMatchWithAdd(u16, u8),
+    LengthMatchWithBinAdd(u16, u8),
+    LengthMatchWithThirdAdd(u16, u8),
+    LengthMatchWithFourthAdd(u16, u8),
+    LengthMatchWithFifthAdd(u16, u8),
+    MaxMatchLength,
+    LongMatchLength(u16),
+}
+
+impl DeflateElementType {
+    fn get_number_additional_bits(&self) -> u8 {
+        match self {
+            JustElement(_) => { 0 }
+            EndBlock => { 0 }
+            LengthMatch(_) => { 0 }
+            LengthMatchWithAdd(_, _) => { 1 }
+            LengthMatchWithBinAdd(_, _) => { 2 }
+            LengthMatchWithThirdAdd(_, _) => { 3 }
+            LengthMatchWithFourthAdd(_, _) => { 4 }
+            LengthMatchWithFifthAdd(_, _) => { 5 }
+            MaxMatchLength => { 0 }
+            LongMatchLength(_) => { 16 }
+        }
+    }
+
+    fn get_additional_bits(&self) -> u16 {
+        match self {
+            JustElement(_) => panic!("JustElement not have additional bits"),
+            EndBlock => panic!("EndBlock not have additional bits"),
+            LengthMatch(_) => panic!("LengthMatch not have additional bits"),
+            LengthMatchWithAdd(_, add) => *add as u16,
+            LengthMatchWithBinAdd(_, add) => *add as u16,
+            LengthMatchWithThirdAdd(_, add) => *add as u16,
+            LengthMatchWithFourthAdd(_, add) => *add as u16,
+            LengthMatchWithFifthAdd(_, add) => *add as u16,
+            MaxMatchLength => panic!("MaxMatchLength not have additional bits"),
+            LongMatchLength(add) => *add,
+        }
+    }
+}
+
+fn deflate_len_to_compared(elem: DeflateLength) -> ComparedElement<DeflateLength> {
+    match elem {
+        DeflateLength::SimpleLength(v) => { ComparedElement::new(elem, v as i32) }
+        DeflateLength::RetryPrevious(_) => { ComparedElement::new(elem, 16) }
+        DeflateLength::RetryZero(_) => { ComparedElement::new(elem, 17) }
+        DeflateLength::BigRetryZero(_) => { ComparedElement::new(elem, 18) }
+    }
+}
+
+fn lz77_elem_to_compared_deflate_elem_type(elem: &Lz77Element, variant: DeflateVariant) -> ComparedElement<DeflateElementType> {
+    match elem {
+        SimpleValue(v) => {
+            ComparedElement::new(JustElement(*v), *v as i32)
+        }
+        ReferenceValue(v) if variant == DeflateVariant::Deflate64 && v.count >= MAX_COINCIDENCE_SIZE => {
+            ComparedElement::new(LongMatchLength((v.count - MIN_COINCIDENCE_SIZE) as u16), 285)
+        }
+        ReferenceValue(v) => {
+            match v.count as u16 {
+                c @ 3..=10 => ComparedElement::new(LengthMatch(c + 254), (c + 254) as i32),
+                c @ 11..=18 => {
+                    ComparedElement::new(LengthMatchWithAdd((c - 1) / 2 + 260, ((c - 1) & 1) as u8),
+                                         ((c - 1) / 2 + 260) as i32)
+                }
+                c @ 19..=34 => {
+                    ComparedElement::new(LengthMatchWithBinAdd((c + 1) / 4 + 264, ((c + 1) % 4) as u8),
+                                         ((c + 1) / 4 + 264) as i32)
+                }
+                c @ 35..=66 => {
+                    ComparedElement::new(LengthMatchWithThirdAdd((c + 5) / 8 + 268, ((c + 5) % 8) as u8),
+                                         ((c + 5) / 8 + 268) as i32)
+                }
+                c @ 67..=130 => {
+                    ComparedElement::new(LengthMatchWithFourthAdd((c - 3) / 16 + 273, ((c - 3) % 16) as u8),
+                                         ((c - 3) / 16 + 273) as i32)
+                }
+                c @ 131..=257 => {
+                    ComparedElement::new(LengthMatchWithFifthAdd((c - 3) / 32 + 277, ((c - 3) % 32) as u8),
+                                         ((c - 3) / 32 + 277) as i32)
+                }
+                258 => ComparedElement::new(MaxMatchLength, 285),
+                _ => panic!("length reference value > 258 or < 3")
+            }
+        }
+    }
+}
+
+fn to_deflate_element_type(id: i32, variant: DeflateVariant) -> Result<DeflateElementType, DecodeError> {
+    match id {
+        0..=255 => Ok(JustElement(id as u8)),
+        256 => Ok(EndBlock),
+        257..=264 => Ok(LengthMatch(id as u16)),
+        265..=268 => Ok(LengthMatchWithAdd(id as u16, 0)),
+        269..=272 => Ok(LengthMatchWithBinAdd(id as u16, 0)),
+        273..=276 => Ok(LengthMatchWithThirdAdd(id as u16, 0)),
+        277..=280 => Ok(LengthMatchWithFourthAdd(id as u16, 0)),
+        281..=284 => Ok(LengthMatchWithFifthAdd(id as u16, 0)),
+        285 if variant == DeflateVariant::Deflate64 => Ok(LongMatchLength(0)),
+        285 => Ok(MaxMatchLength),
+        _ => Err(DecodeError::InvalidSymbol(id))
+    }
+}
+
+fn get_deflate_copy_length(elem: &DeflateElementType) -> u32 {
+    match elem {
+        JustElement(_) => panic!("JustElement not have copy length"),
+        EndBlock => panic!("EndBlock not have copy length"),
+        LengthMatch(l) => { *l as u32 - 254 }
+        LengthMatchWithAdd(l, add) => { ((*l as u32 - 265) << 1) + 11 + *add as u32 }
+        LengthMatchWithBinAdd(l, add) => { ((*l as u32 - 269) << 2) + 19 + *add as u32 }
+        LengthMatchWithThirdAdd(l, add) => { ((*l as u32 - 273) << 3) + 35 + *add as u32 }
+        LengthMatchWithFourthAdd(l, add) => { ((*l as u32 - 277) << 4) + 67 + *add as u32 }
+        LengthMatchWithFifthAdd(l, add) => { ((*l as u32 - 281) << 5) + 131 + *add as u32 }
+        MaxMatchLength => { MAX_COINCIDENCE_SIZE }
+        LongMatchLength(add) => { MIN_COINCIDENCE_SIZE + *add as u32 }
+    }
+}
+
+
+fn encoding_sequence_length(sequence_of_length: &[i32]) -> Vec<DeflateLength> {
+    let mut answer = Vec::new();
+
+    let mut number_of_zeros = 0;
+
+    let push_zeros = |answer: &mut Vec<DeflateLength>, mut number_of_zeros: usize| {
+        while number_of_zeros >= 3 {
+            if number_of_zeros > 11 {
+                answer.push(DeflateLength::BigRetryZero(min(138, number_of_zeros) as u8));
+                number_of_zeros -= min(138, number_of_zeros);
+            } else {
+                answer.push(DeflateLength::RetryZero(min(10, number_of_zeros) as u8));
+                number_of_zeros -= min(10, number_of_zeros);
+            }
+        }
+        while number_of_zeros != 0 {
+            answer.push(DeflateLength::SimpleLength(0));
+            number_of_zeros -= 1;
+        }
+    };
+
+    let mut i = 0;
+    while i < sequence_of_length.len() {
+        if sequence_of_length[i] == 0 {
+            number_of_zeros += 1;
+        }
+        if sequence_of_length[i] != 0 {
+            push_zeros(&mut answer, number_of_zeros);
+            number_of_zeros = 0;
+
+            if i > 0 && sequence_of_length[i] == sequence_of_length[i - 1] {
+                let mut count = 1;
+                while i + 1 < sequence_of_length.len() &&
+                    count < 6 && sequence_of_length[i] == sequence_of_length[i + 1] {
+                    count += 1;
+                    i += 1;
+                }
+                if count >= 3 {
+                    answer.push(DeflateLength::RetryPrevious(count));
+                } else {
+                    for _ in 0..count {
+                        answer.push(DeflateLength::SimpleLength(sequence_of_length[i] as u8));
+                    }
+                }
+            } else {
+                answer.push(DeflateLength::SimpleLength(sequence_of_length[i] as u8));
+            }
+        }
+        i += 1;
+    }
+    push_zeros(&mut answer, number_of_zeros);
+
+    answer
+}
+
+
+fn huffman_encoding<T: Clone>(max_depth: i32, lang_size: usize, data: &[ComparedElement<T>]) ->
+(HashMap<ComparedElement<T>, Vec<bool>>,
+ Vec<i32>) {
+    let mut lang = Vec::new();
+    let mut repetition_counter = vec![0; lang_size];
+    for val in data {
+        repetition_counter[val.sorting_value as usize] += 1;
+
+        if repetition_counter[val.sorting_value as usize] == 1 {
+            lang.push(val);
+        }
+    }
+
+    // inflaters reject an incomplete code, so a lone symbol gets an unused neighbour
+    if lang.len() == 1 {
+        let unused = if repetition_counter[0] == 0 { 0 } else { 1 };
+        repetition_counter[unused] = 1;
+    }
+
+    let required_data = repetition_counter
+        .iter()
+        .filter_map(|x| if *x == 0 { None } else { Some(*x) })
+        .collect::<Vec<i32>>();
+
+    let required_lengths = if required_data.is_empty() {
+        Vec::new()
+    } else {
+        bounded_huffman(max_depth, required_data.as_slice())
+    };
+
+    let mut i = 0;
+    let all_lengths = repetition_counter
+        .iter()
+        .map(|x| {
+            if *x == 0 {
+                0
+            } else {
+                i += 1;
+                required_lengths[i - 1]
+            }
+        })
+        .collect::<Vec<i32>>();
+
+    let symbol_codes = lengths_to_huffman_codes(all_lengths.as_slice()).0;
+
+    let bin_codes = lang
+        .into_iter()
+        .map(|val| (val.clone(), symbol_codes[&val.sorting_value].clone()))
+        .collect::<HashMap<ComparedElement<T>, Vec<bool>>>();
+
+    (bin_codes, all_lengths)
+}
+
+// the output the one-shot decoders start with, it grows with the data decoded so far,
+// so that short streams such as the candidates of a scan stay cheap
+const DECODING_CHUNK_SIZE: usize = 1 << 10;
+
+/// The preset dictionary only seeds the history that back references may point into,
+/// it is not a part of the returned data.
+fn deflate_stream_decoding_with_dictionary(data: &[u8], dictionary: &[u8], variant: DeflateVariant)
+                                           -> Result<(Vec<u8>, usize), DecodeError> {
+    let mut inflater = Inflater::with_dictionary(dictionary, variant);
+    let mut answer = Vec::new();
+
+    loop {
+        let size = answer.len();
+        answer.resize(2 * size + DECODING_CHUNK_SIZE, 0);
+        let status = inflater.feed(&data[inflater.total_in() as usize..], &mut answer[size..])?;
+        answer.truncate(inflater.total_out() as usize);
+
+        match status {
+            Status::NeedsInput => return Err(DecodeError::UnexpectedEnd),
+            Status::OutputFull => {}
+            Status::Done => return Ok((answer, inflater.total_in() as usize)),
+        }
+    }
+}
+
+fn deflate_stream_decoding(data: &[u8], variant: DeflateVariant) -> Result<(Vec<u8>, usize), DecodeError> {
+    deflate_stream_decoding_with_dictionary(data, &[], variant)
+}
+
+fn deflate_decoding(data: Vec<u8>, variant: DeflateVariant) -> Result<Vec<u8>, DecodeError> {
+    Ok(deflate_stream_decoding(data.as_slice(), variant)?.0)
+}
+
+fn deflate_block_encoding(lz77_data: &[Lz77Element], is_end_block: bool, variant: DeflateVariant) -> Vec<bool> {
+    let mut bin_result = Vec::new();
+
+    let mut deflate_elements = lz77_data
+        .iter()
+        .map(|x| lz77_elem_to_compared_deflate_elem_type(x, variant))
+        .collect::<Vec<ComparedElement<DeflateElementType>>>();
+    deflate_elements.push(ComparedElement::new(EndBlock, 256));
+
+    let (_, all_deflate_lengths) = huffman_encoding(15,
+                                                    286,
+                                                    deflate_elements.as_slice());
+
+    let offset_elements = lz77_data
+        .iter()
+        .filter_map(|x| match x {
+            SimpleValue(_) => { None }
+            ReferenceValue(v) => {
+                let offset = DeflateOffset::new(v.offset);
+                let compared_value = offset.main_value;
+                Some(ComparedElement::new(offset, compared_value as i32))
+            }
+        }).collect::<Vec<ComparedElement<DeflateOffset>>>();
+
+    let (_, all_offset_lengths) = huffman_encoding(15,
+                                                   variant.number_offset_codes() as usize,
+                                                   offset_elements.as_slice());
+
+    let hlit = max(all_deflate_lengths.iter().rposition(|x| *x != 0).unwrap_or(0), 256) + 1;
+    let hdist = all_offset_lengths.iter().rposition(|x| *x != 0).unwrap_or(0) + 1;
+
+    let mut encoded_sequence_lengths = encoding_sequence_length(
+        &all_deflate_lengths[..hlit]);
+
+    let encoded_sequence_offset_lengths = encoding_sequence_length(
+        &all_offset_lengths[..hdist]);
+
+    encoded_sequence_lengths.extend(encoded_sequence_offset_lengths);
+
+    let deflate_length_elements = encoded_sequence_lengths
+        .iter()
+        .map(|x| deflate_len_to_compared(x.clone()))
+        .collect::<Vec<ComparedElement<DeflateLength>>>();
+
+    let (bin_deflate_len_codes, all_deflate_len_lengths) = huffman_encoding(7,
+                                                                            19,
+                                                                            deflate_length_elements.as_slice());
+
+    let mut hclen = SEQUENCE_LENGTH_COMMAND.len() - 4;
+    for v in SEQUENCE_LENGTH_COMMAND.iter().rev() {
+        if all_deflate_len_lengths[*v as usize] == 0 {
+            hclen -= 1;
+        } else {
+            break;
+        }
+    }
+
+    bin_result.push(is_end_block);
+    bin_result.append(&mut to_const_size_lsb_bin(2, 2));
+
+    bin_result.append(&mut to_const_size_lsb_bin(hlit as i32 - 257, 5));
+    bin_result.append(&mut to_const_size_lsb_bin(hdist as i32 - 1, 5));
+    bin_result.append(&mut to_const_size_lsb_bin(hclen as i32, 4));
+
+    for length_index in &SEQUENCE_LENGTH_COMMAND[..hclen + 4] {
+        let mut bin_value = to_const_size_lsb_bin(all_deflate_len_lengths[*length_index as usize], 3);
+        bin_result.append(&mut bin_value)
+    }
+
+    encoded_sequence_lengths.into_iter().for_each(|v| {
+        bin_result.extend(bin_deflate_len_codes.get(&deflate_len_to_compared(v.clone())).unwrap());
+
+        match v {
+            DeflateLength::RetryPrevious(v) => {
+                bin_result.extend(to_const_size_lsb_bin(v as i32 - 3, 2))
+            }
+            DeflateLength::RetryZero(v) => {
+                bin_result.extend(to_const_size_lsb_bin(v as i32 - 3, 3))
+            }
+            DeflateLength::BigRetryZero(v) => {
+                bin_result.extend(to_const_size_lsb_bin(v as i32 - 11, 7))
+            }
+            _ => ()
+        };
+    });
+
+    let deflate_codes = lengths_to_huffman_codes(all_deflate_lengths.as_slice()).0;
+    let offset_codes = lengths_to_huffman_codes(all_offset_lengths.as_slice()).0;
+    lz77_elements_encoding(&mut bin_result, lz77_data, &deflate_codes, &offset_codes, variant);
+
+    bin_result
+}
+
+fn fixed_huffman_lengths() -> (Vec<i32>, Vec<i32>) {
+    let mut deflate_lengths = vec![8; 288];
+    deflate_lengths[144..256].fill(9);
+    deflate_lengths[256..280].fill(7);
+
+    (deflate_lengths, vec![5; 32])
+}
+
+fn fixed_block_encoding(lz77_data: &[Lz77Element], is_end_block: bool, variant: DeflateVariant) -> Vec<bool> {
+    let mut bin_result = vec![is_end_block];
+    bin_result.append(&mut to_const_size_lsb_bin(1, 2));
+
+    let (deflate_lengths, offset_lengths) = fixed_huffman_lengths();
+    let deflate_codes = lengths_to_huffman_codes(deflate_lengths.as_slice()).0;
+    let offset_codes = lengths_to_huffman_codes(offset_lengths.as_slice()).0;
+    lz77_elements_encoding(&mut bin_result, lz77_data, &deflate_codes, &offset_codes, variant);
+
+    bin_result
+}
+
+fn lz77_elements_encoding(bin_result: &mut Vec<bool>, lz77_data: &[Lz77Element],
+                          deflate_codes: &HashMap<i32, Vec<bool>>, offset_codes: &HashMap<i32, Vec<bool>>,
+                          variant: DeflateVariant) {
+    for value in lz77_data {
+        let compared_deflate_elem = lz77_elem_to_compared_deflate_elem_type(value, variant);
+        bin_result.extend(&deflate_codes[&compared_deflate_elem.sorting_value]);
+
+        if let ReferenceValue(v) = value {
+            let number_additional_bits_for_match = compared_deflate_elem.value.get_number_additional_bits() as i32;
+
+            if number_additional_bits_for_match != 0 {
+                bin_result.extend(to_const_size_lsb_bin(compared_deflate_elem.value.get_additional_bits() as i32,
+                                                        number_additional_bits_for_match));
+            }
+
+            let offset = DeflateOffset::new(v.offset);
+            let compared_value = offset.main_value;
+            let additional_bits = offset.additional_bits as i32;
+            let number_additional_bits_for_offset = offset.get_number_additional_bits() as i32;
+
+            bin_result.extend(&offset_codes[&(compared_value as i32)]);
+
+            if number_additional_bits_for_offset != 0 {
+                bin_result.extend(to_const_size_lsb_bin(additional_bits, number_additional_bits_for_offset));
+            }
+        }
+    }
+    bin_result.extend(&deflate_codes[&256]);
+}
+
+fn stored_block_encoding(data: &[u8], is_end_block: bool, bit_position: usize) -> Vec<bool> {
+    let mut bin_result = Vec::new();
+
+    let mut start_chunk = 0;
+    loop {
+        let end_chunk = min(start_chunk + MAX_STORED_BLOCK_SIZE, data.len());
+        let is_last_chunk = end_chunk == data.len();
+        let chunk = &data[start_chunk..end_chunk];
+
+        bin_result.push(is_end_block && is_last_chunk);
+        bin_result.append(&mut to_const_size_lsb_bin(0, 2));
+        while !(bit_position + bin_result.len()).is_multiple_of(8) {
+            bin_result.push(false);
+        }
+
+        bin_result.append(&mut to_const_size_lsb_bin(chunk.len() as i32, 16));
+        bin_result.append(&mut to_const_size_lsb_bin(!(chunk.len() as i32) & 0xffff, 16));
+        bin_result.append(&mut bytes_to_bin(chunk));
+
+        if is_last_chunk {
+            break;
+        }
+        start_chunk = end_chunk;
+    }
+
+    bin_result
+}
+
+/// The size in bits of `stored_block_encoding`, without expanding the data into bits.
+fn stored_block_size(size: usize, bit_position: usize) -> usize {
+    let mut answer = 0;
+    for _ in 0..size.div_ceil(MAX_STORED_BLOCK_SIZE).max(1) {
+        answer += 3;
+        answer += (8 - (bit_position + answer) % 8) % 8;
+        answer += 32;
+    }
+
+    answer + 8 * size
+}
+
+/// Each block is encoded in all three ways and the shortest one is kept. Only the position
+/// of the block in the stream modulo 8 matters, for the padding of stored blocks.
+/// A block of long matches covers megabytes of data, so the stored block is encoded only
+/// when its size shows that it is the shortest.
+fn smallest_block_encoding(lz77_data: &[Lz77Element], data: &[u8], is_end_block: bool, bit_position: usize,
+                           variant: DeflateVariant) -> Vec<bool> {
+    let dynamic_block = deflate_block_encoding(lz77_data, is_end_block, variant);
+    let fixed_block = fixed_block_encoding(lz77_data, is_end_block, variant);
+    let block = if fixed_block.len() < dynamic_block.len() { fixed_block } else { dynamic_block };
+
+    if stored_block_size(data.len(), bit_position) < block.len() {
+        stored_block_encoding(data, is_end_block, bit_position)
+    } else {
+        block
+    }
+}
+
+fn deflate_encoding(data: Vec<u8>, variant: DeflateVariant) -> Vec<bool> {
+    deflate_encoding_with_dictionary(data, &[], variant)
+}
+
+/// A bare RFC 1951 stream, without a container.
+pub fn raw_deflate_encoding(data: Vec<u8>, variant: DeflateVariant) -> Vec<u8> {
+    bin_to_bytes(&deflate_encoding(data, variant))
+}
+
+fn deflate_encoding_with_dictionary(data: Vec<u8>, dictionary: &[u8], variant: DeflateVariant) -> Vec<bool> {
+    deflate_encoding_with_level(data, dictionary, CompressionLevel::Default, variant)
+}
+
+fn deflate_encoding_with_level(data: Vec<u8>, dictionary: &[u8], level: CompressionLevel,
+                               variant: DeflateVariant) -> Vec<bool> {
+    let lz77_result = encoding_lz77(&data, dictionary, variant.max_shift(), variant.max_coincidence_size(),
+                                    level.max_chain_length(), level.insert_match_positions());
+
+    let mut bin_result: Vec<bool> = Vec::new();
+
+    let mut start_block = 0;
+    let mut start_data = 0;
+    loop {
+        let end_block = min(start_block + MAX_BLOCK_ELEMENTS, lz77_result.len());
+        let is_end_block = end_block == lz77_result.len();
+
+        let lz77_local = &lz77_result[start_block..end_block];
+        let end_data = start_data + lz77_local.iter().map(|x| x.uncompressed_size()).sum::<usize>();
+
+        let block = smallest_block_encoding(lz77_local, &data[start_data..end_data], is_end_block, bin_result.len(),
+                                            variant);
+        bin_result.extend(block);
+
+        if is_end_block {
+            break;
+        }
+        start_block = end_block;
+        start_data = end_data;
+    }
+
+    bin_result
+}
+
+/// How hard the encoder looks for matches. Both levels write the same format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// Short match chains, the level of [`compress`] and the `dzip` tool.
+    Default,
+    /// Long match chains, for data written once and read many times.
+    Best,
+}
+
+impl CompressionLevel {
+    fn max_chain_length(&self) -> usize {
+        match self {
+            CompressionLevel::Default => DEFAULT_MAX_CHAIN_LENGTH,
+            CompressionLevel::Best => BEST_MAX_CHAIN_LENGTH,
+        }
+    }
+
+    fn insert_match_positions(&self) -> bool {
+        *self == CompressionLevel::Best
+    }
+}
+
+/// Decompresses data in any format `dzip -d` detects, like [`decompress`], with a preset
+/// dictionary for zlib and `.dzip` data. `variant` applies only to deflate streams without
+/// a header, which do not name it.
+pub fn decompress_data(data: Vec<u8>, dictionary: Option<&[u8]>, variant: DeflateVariant) -> Result<Vec<u8>, DecodeError> {
+    if is_gzip(&data) {
+        gzip_decoding(&data)
+    } else if is_zlib(&data) {
+        zlib_decoding(&data, dictionary)
+    } else if is_lzw(&data) {
+        lzw_decoding(&data)
+    } else {
+        dzip_decoding(data, dictionary, variant)
+    }
+}
+
+/// Compresses the data into the `.dzip` format, the same output as running `dzip` on a file:
+/// a header with the size and CRC-32 of the data followed by a deflate stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    dzip_encoding(data.to_vec(), None, DeflateVariant::Deflate)
+}
+
+/// Decompresses data produced by [`compress`] or the `dzip` tool. The format is detected
+/// like `dzip -d` does: gzip, zlib and `compress(1)` `.Z` data is decoded as well.
+///
+/// Data compressed with a preset dictionary cannot be decoded, it fails with
+/// [`DecodeError::DictionaryRequired`].
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    decompress_data(data.to_vec(), None, DeflateVariant::Deflate)
+}
+
+/// Compresses the data into a zlib stream (RFC 1950), the format embedded in PDF streams,
+/// PNG images and git objects. With a preset dictionary, back references may point into it
+/// and the stream records its Adler-32; the same dictionary is needed to decompress it.
+///
+/// ```
+/// let dictionary = b"hello world".as_slice();
+/// let compressed = dzip::zlib_compress(b"hello hello world", Some(dictionary));
+/// assert_eq!(dzip::zlib_decompress(&compressed, Some(dictionary)).unwrap(), b"hello hello world");
+/// assert_eq!(dzip::zlib_decompress(&compressed, None), Err(dzip::DecodeError::DictionaryRequired));
+/// ```
+pub fn zlib_compress(data: &[u8], dictionary: Option<&[u8]>) -> Vec<u8> {
+    zlib_encoding(data.to_vec(), dictionary)
+}
+
+/// Decompresses a zlib stream and checks its Adler-32. A dictionary given for a stream that
+/// was compressed without one is ignored; the data after the stream is ignored as well.
+pub fn zlib_decompress(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, DecodeError> {
+    zlib_decoding(data, dictionary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lz77::Lz77ReferenceElement;
+    use crate::test_data::random_bytes;
+
+    fn deflate64_lz77(data: &[u8]) -> Vec<Lz77Element> {
+        let (level, variant) = (CompressionLevel::Default, DeflateVariant::Deflate64);
+        encoding_lz77(data, &[], variant.max_shift(), variant.max_coincidence_size(), level.max_chain_length(),
+                      level.insert_match_positions())
+    }
+
+    fn references(lz77_data: &[Lz77Element]) -> impl Iterator<Item = &Lz77ReferenceElement> {
+        lz77_data.iter().filter_map(|x| match x {
+            ReferenceValue(v) => Some(v),
+            SimpleValue(_) => None,
+        })
+    }
+
+    #[test]
+    fn deflate64_long_lengths() {
+        let mut data = b"a run of one byte: ".to_vec();
+        data.extend([b'z'; 200000]);
+        let lz77_data = deflate64_lz77(&data);
+        // length code 285 takes 16 extra bits, up to the longest match
+        assert!(references(&lz77_data).any(|x| x.count == MAX_DEFLATE64_COINCIDENCE_SIZE));
+        assert!(references(&lz77_data).any(|x| x.count > MAX_COINCIDENCE_SIZE && x.count < MAX_DEFLATE64_COINCIDENCE_SIZE));
+
+        for block in [deflate_block_encoding(&lz77_data, true, DeflateVariant::Deflate64),
+                      fixed_block_encoding(&lz77_data, true, DeflateVariant::Deflate64)] {
+            assert_eq!(deflate_decoding(bin_to_bytes(&block), DeflateVariant::Deflate64).unwrap(), data);
+        }
+        let compressed = raw_deflate_encoding(data.clone(), DeflateVariant::Deflate64);
+        assert!(compressed.len() < 100);
+        assert_eq!(deflate_decoding(compressed, DeflateVariant::Deflate64).unwrap(), data);
+    }
+
+    #[test]
+    fn deflate64_long_distances() {
+        // copies 40000 and 50000 bytes back, distance codes 30 and 31
+        let (first, second) = (random_bytes(40000, 1), random_bytes(50000, 2));
+        let data = [first.as_slice(), &first, &second, &second].concat();
+        let lz77_data = deflate64_lz77(&data);
+        let codes = references(&lz77_data).map(|x| DeflateOffset::new(x.offset).main_value).collect::<Vec<_>>();
+        assert!(codes.contains(&30) && codes.contains(&31));
+
+        let compressed = raw_deflate_encoding(data.clone(), DeflateVariant::Deflate64);
+        assert!(compressed.len() < 100000);
+        assert_eq!(deflate_decoding(compressed.clone(), DeflateVariant::Deflate64).unwrap(), data);
+
+        // plain deflate has 30 distance codes
+        let error = deflate_decoding(compressed, DeflateVariant::Deflate).unwrap_err();
+        assert!(matches!(error, DecodeError::InvalidSymbol(30 | 31)), "{:?}", error);
+    }
+
+    #[test]
+    fn stored_block_size_matches_encoding() {
+        for size in [0, 1, 1000, MAX_STORED_BLOCK_SIZE, MAX_STORED_BLOCK_SIZE + 1, 3 * MAX_STORED_BLOCK_SIZE + 7] {
+            let data = vec![7; size];
+            for bit_position in 0..8 {
+                assert_eq!(stored_block_size(size, bit_position), stored_block_encoding(&data, true, bit_position).len());
+            }
+        }
+    }
+}
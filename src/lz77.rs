@@ -1,13 +1,17 @@
+use std::cmp::min;
 use std::collections::{HashMap, LinkedList};
 
-pub const MAX_SHIFT: u16 = 2 << 15 - 1;
-pub const MAX_COINCIDENCE_SIZE: u16 = 258;
-pub const MIN_COINCIDENCE_SIZE: u16 = 3;
+pub const MAX_SHIFT: u32 = 2 << (15 - 1);
+pub const MAX_COINCIDENCE_SIZE: u32 = 258;
+pub const MIN_COINCIDENCE_SIZE: u32 = 3;
+
+pub const MAX_DEFLATE64_SHIFT: u32 = 2 << 15;
+pub const MAX_DEFLATE64_COINCIDENCE_SIZE: u32 = 65538;
 
 
 pub struct Lz77ReferenceElement {
-    pub offset: u16,
-    pub count: u16,
+    pub offset: u32,
+    pub count: u32,
 }
 
 pub enum Lz77Element {
@@ -15,69 +19,192 @@ pub enum Lz77Element {
     ReferenceValue(Lz77ReferenceElement),
 }
 
+impl Lz77Element {
+    pub fn uncompressed_size(&self) -> usize {
+        match self {
+            Lz77Element::SimpleValue(_) => 1,
+            Lz77Element::ReferenceValue(v) => v.count as usize,
+        }
+    }
+}
 
-pub fn encoding_lz77(data: &Vec<u8>) -> Vec<Lz77Element> {
-    let mut answer: Vec<Lz77Element> = Vec::new();
-    let mut lib: HashMap<[u8; MIN_COINCIDENCE_SIZE as usize], LinkedList<usize>> = HashMap::new();
 
-    let mut i = 0;
-    while i < data.len() {
+fn lib_key(data: &[u8], index: usize) -> [u8; MIN_COINCIDENCE_SIZE as usize] {
+    let mut key: [u8; MIN_COINCIDENCE_SIZE as usize] = Default::default();
+    key.clone_from_slice(&data[index..index + MIN_COINCIDENCE_SIZE as usize]);
+    key
+}
 
-        if i + (MIN_COINCIDENCE_SIZE as usize) - 1 < data.len() {
-            let initial_i = i;
-            match lib.get_mut(&data[i..i + MIN_COINCIDENCE_SIZE as usize]) {
-                Some(start_index) => {
-                    let mut max_slice = 0;
-                    let mut index_slice = 0;
+type Lib = HashMap<[u8; MIN_COINCIDENCE_SIZE as usize], LinkedList<usize>>;
 
-                    while start_index.len() > 10 {
-                        start_index.pop_front();
-                    }
+/// Finds matches in data that arrives in parts. Only the last `max_shift` bytes are kept,
+/// and a position is encoded only once the longest match it may have is buffered, so the
+/// result does not depend on how the data is split.
+///
+/// `max_chain_length` limits the number of earlier positions checked for each match.
+/// With `insert_match_positions` every position inside a match is added to the hash table,
+/// not only the first one, which finds more matches at the cost of speed.
+pub struct Lz77Encoder {
+    window: Vec<u8>,
+    // the position of the first byte of the window from the start of the dictionary
+    window_start: usize,
+    position: usize,
+    dictionary_size: usize,
+    seeded_size: usize,
+    lib: Lib,
+    max_shift: u32,
+    max_coincidence_size: u32,
+    max_chain_length: usize,
+    insert_match_positions: bool,
+}
+
+impl Lz77Encoder {
+    pub fn new(dictionary: &[u8], max_shift: u32, max_coincidence_size: u32, max_chain_length: usize,
+               insert_match_positions: bool) -> Lz77Encoder {
+        Lz77Encoder {
+            window: dictionary.to_vec(),
+            window_start: 0,
+            position: dictionary.len(),
+            dictionary_size: dictionary.len(),
+            seeded_size: 0,
+            lib: HashMap::new(),
+            max_shift,
+            max_coincidence_size,
+            max_chain_length,
+            insert_match_positions,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8], answer: &mut Vec<Lz77Element>) {
+        for chunk in data.chunks(self.max_shift as usize) {
+            self.window.extend_from_slice(chunk);
+            self.encode(false, answer);
+            self.discard_history();
+        }
+    }
+
+    pub fn finish(&mut self, answer: &mut Vec<Lz77Element>) {
+        self.encode(true, answer);
+    }
+
+    fn data_size(&self) -> usize {
+        self.window_start + self.window.len()
+    }
+
+    /// Until the end of the data is known, a position needs a whole match and the keys of
+    /// all the positions inside it.
+    fn is_encodable(&self, is_end: bool) -> bool {
+        if is_end {
+            self.position < self.data_size()
+        } else {
+            self.position + (self.max_coincidence_size + MIN_COINCIDENCE_SIZE) as usize <= self.data_size()
+        }
+    }
+
+    fn encode(&mut self, is_end: bool, answer: &mut Vec<Lz77Element>) {
+        let data_size = self.data_size();
+        let data = &self.window;
+        let offset = self.window_start;
 
-                    for current_start_index_slice in start_index.iter().rev() {
-                        if i - current_start_index_slice <= MAX_SHIFT as usize {
-                            let mut current_slice_size = MIN_COINCIDENCE_SIZE;
+        while self.seeded_size < self.dictionary_size && self.seeded_size + MIN_COINCIDENCE_SIZE as usize <= data_size {
+            self.lib.entry(lib_key(data, self.seeded_size - offset)).or_default().push_back(self.seeded_size);
+            self.seeded_size += 1;
+        }
+
+        while self.is_encodable(is_end) {
+            let initial_i = self.position;
+            let mut i = initial_i;
+
+            if i + (MIN_COINCIDENCE_SIZE as usize) - 1 < data_size {
+                match self.lib.get_mut(&data[i - offset..i - offset + MIN_COINCIDENCE_SIZE as usize]) {
+                    Some(start_index) => {
+                        let mut max_slice = 0;
+                        let mut index_slice = 0;
+
+                        while start_index.len() > self.max_chain_length {
+                            start_index.pop_front();
+                        }
+
+                        for current_start_index_slice in start_index.iter().rev() {
+                            if i - current_start_index_slice <= self.max_shift as usize {
+                                let mut current_slice_size = MIN_COINCIDENCE_SIZE;
 
-                            for delta in MIN_COINCIDENCE_SIZE as usize..data.len() - i {
+                                for delta in MIN_COINCIDENCE_SIZE as usize..data_size - i {
 
-                                if data[i + delta] == data[current_start_index_slice + delta] &&
-                                    current_slice_size < MAX_COINCIDENCE_SIZE {
-                                    current_slice_size += 1;
-                                } else {
-                                    break;
+                                    if data[i + delta - offset] == data[current_start_index_slice + delta - offset] &&
+                                        current_slice_size < self.max_coincidence_size {
+                                        current_slice_size += 1;
+                                    } else {
+                                        break;
+                                    }
+                                }
+                                if current_slice_size > max_slice {
+                                    max_slice = current_slice_size;
+                                    index_slice = *current_start_index_slice;
                                 }
-                            }
-                            if current_slice_size as u16 > max_slice {
-                                max_slice = current_slice_size as u16;
-                                index_slice = *current_start_index_slice as u16;
                             }
                         }
+                        if max_slice != 0 {
+                            answer.push(Lz77Element::ReferenceValue(
+                                Lz77ReferenceElement {
+                                    offset: (initial_i - index_slice) as u32,
+                                    count: max_slice,
+                                }
+                            ));
+                            i += max_slice as usize;
+                        } else {
+                            answer.push(Lz77Element::SimpleValue(data[initial_i - offset]));
+                            i += 1;
+                        }
                     }
-                    if max_slice != 0 {
-                        answer.push(Lz77Element::ReferenceValue(
-                            Lz77ReferenceElement {
-                                offset: (initial_i - index_slice as usize) as u16,
-                                count: max_slice,
-                            }
-                        ));
-                        i += max_slice as usize;
-                    } else {
-                        answer.push(Lz77Element::SimpleValue(data[initial_i]));
+                    None => {
+                        answer.push(Lz77Element::SimpleValue(data[initial_i - offset]));
                         i += 1;
                     }
                 }
-                None => {
-                    answer.push(Lz77Element::SimpleValue(data[initial_i]));
-                    i += 1;
+                if self.insert_match_positions {
+                    for position in initial_i..min(i, data_size + 1 - MIN_COINCIDENCE_SIZE as usize) {
+                        self.lib.entry(lib_key(data, position - offset)).or_default().push_back(position);
+                    }
+                } else {
+                    self.lib.entry(lib_key(data, initial_i - offset)).or_default().push_back(initial_i);
                 }
+            } else {
+                answer.push(Lz77Element::SimpleValue(data[i - offset]));
+                i += 1;
             }
-            let mut key: [u8; MIN_COINCIDENCE_SIZE as usize] = Default::default();
-            key.clone_from_slice(&data[initial_i..initial_i + MIN_COINCIDENCE_SIZE as usize]);
-            lib.entry(key).or_insert(LinkedList::new()).push_back(initial_i);
-        } else {
-            answer.push(Lz77Element::SimpleValue(data[i]));
-            i += 1;
+            self.position = i;
         }
     }
-    return answer;
+
+    /// Positions further back than `max_shift` can not be referenced anymore. Dropping them
+    /// from the hash table does not change the matches: only the last `max_chain_length`
+    /// positions of a key are checked, and the dropped ones are always the oldest.
+    fn discard_history(&mut self) {
+        let history_start = self.position.saturating_sub(self.max_shift as usize);
+        if history_start < self.window_start + self.max_shift as usize {
+            return;
+        }
+
+        self.window.drain(..history_start - self.window_start);
+        self.window_start = history_start;
+        self.lib.retain(|_, positions| {
+            while positions.front().is_some_and(|x| *x < history_start) {
+                positions.pop_front();
+            }
+            !positions.is_empty()
+        });
+    }
+}
+
+pub fn encoding_lz77(data: &[u8], dictionary: &[u8], max_shift: u32, max_coincidence_size: u32,
+                     max_chain_length: usize, insert_match_positions: bool) -> Vec<Lz77Element> {
+    let mut answer = Vec::new();
+
+    let mut encoder = Lz77Encoder::new(dictionary, max_shift, max_coincidence_size, max_chain_length,
+                                       insert_match_positions);
+    encoder.push(data, &mut answer);
+    encoder.finish(&mut answer);
+
+    answer
 }
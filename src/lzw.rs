@@ -0,0 +1,234 @@
+//! The LZW format of `compress(1)`, the `.Z` files.
+
+use std::collections::HashMap;
+
+use crate::bin_num::{bin_to_bytes, bytes_to_bin, lsb_bin_to_num, to_const_size_lsb_bin};
+use crate::error::DecodeError;
+
+const LZW_MAGIC: [u8; 2] = [0x1f, 0x9d];
+const LZW_HEADER_SIZE: usize = 3;
+const FLAG_BLOCK_MODE: u8 = 0x80;
+const MAX_BITS_MASK: u8 = 0x1f;
+
+const MIN_BITS: usize = 9;
+const MAX_BITS: usize = 16;
+const CLEAR_CODE: usize = 256;
+// the input size between two checks whether clearing the table would compress better
+const CHECK_GAP: usize = 10000;
+
+/// Returns true if the data starts with the `.Z` magic number.
+pub fn is_lzw(data: &[u8]) -> bool {
+    data.starts_with(&LZW_MAGIC)
+}
+
+/// The largest code that fits into the width, the last width is allowed to fill its table.
+/// Like in `compress(1)`, the initial width never counts as the last one, so 9-bit files
+/// switch to 10-bit codes once their table is full.
+fn max_code(bits: usize, max_bits: usize) -> usize {
+    if bits == max_bits && bits > MIN_BITS { 1 << max_bits } else { (1 << bits) - 1 }
+}
+
+/// `compress(1)` reads and writes codes in groups of eight, so when the code width changes
+/// or the table is cleared the rest of the current group is skipped.
+fn group_end(position: usize, group_start: usize, bits: usize) -> usize {
+    group_start + (position - group_start).div_ceil(bits * 8) * bits * 8
+}
+
+struct LzwWriter {
+    bin_result: Vec<bool>,
+    bits: usize,
+    group_start: usize,
+}
+
+impl LzwWriter {
+    fn write_code(&mut self, code: usize) {
+        self.bin_result.append(&mut to_const_size_lsb_bin(code as i32, self.bits as i32));
+    }
+
+    fn set_bits(&mut self, bits: usize) {
+        let end = group_end(self.bin_result.len(), self.group_start, self.bits);
+        self.bin_result.resize(end, false);
+        self.group_start = end;
+        self.bits = bits;
+    }
+}
+
+/// Always uses 16-bit codes in block mode, like `compress -b 16`. Once the table is full
+/// it is cleared when the compression ratio starts to drop.
+pub fn lzw_encoding(data: &[u8]) -> Vec<u8> {
+    let mut answer = LZW_MAGIC.to_vec();
+    answer.push(FLAG_BLOCK_MODE | MAX_BITS as u8);
+
+    let Some((&first, rest)) = data.split_first() else {
+        return answer;
+    };
+
+    let mut writer = LzwWriter {
+        bin_result: Vec::new(),
+        bits: MIN_BITS,
+        group_start: 0,
+    };
+    let mut table: HashMap<(usize, u8), usize> = HashMap::new();
+    let mut free_code = CLEAR_CODE + 1;
+    let mut checkpoint = CHECK_GAP;
+    let mut ratio = 0.0;
+
+    let mut current = first as usize;
+    for (i, &byte) in rest.iter().enumerate() {
+        if let Some(&code) = table.get(&(current, byte)) {
+            current = code;
+            continue;
+        }
+
+        writer.write_code(current);
+        if free_code > max_code(writer.bits, MAX_BITS) {
+            writer.set_bits(writer.bits + 1);
+        }
+
+        if free_code < 1 << MAX_BITS {
+            table.insert((current, byte), free_code);
+            free_code += 1;
+        } else if i + 1 >= checkpoint {
+            let size = i + 1;
+            checkpoint = size + CHECK_GAP;
+            let current_ratio = size as f64 / (writer.bin_result.len() as f64 / 8.0);
+            if current_ratio > ratio {
+                ratio = current_ratio;
+            } else {
+                ratio = 0.0;
+                table.clear();
+                free_code = CLEAR_CODE + 1;
+                writer.write_code(CLEAR_CODE);
+                writer.set_bits(MIN_BITS);
+            }
+        }
+        current = byte as usize;
+    }
+    writer.write_code(current);
+
+    answer.extend(bin_to_bytes(&writer.bin_result));
+    answer
+}
+
+fn read_code(data: &[bool], read_index: &mut usize, bits: usize) -> Option<usize> {
+    if *read_index + bits > data.len() {
+        return None;
+    }
+    let code = lsb_bin_to_num(&data[*read_index..*read_index + bits]) as usize;
+    *read_index += bits;
+
+    Some(code)
+}
+
+/// Decodes files written with any maximum code width from 9 to 16 bits, with or without
+/// block mode.
+pub fn lzw_decoding(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < LZW_HEADER_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if !is_lzw(data) {
+        return Err(DecodeError::InvalidHeader("not a compress file"));
+    }
+    let is_block_mode = data[2] & FLAG_BLOCK_MODE != 0;
+    let max_bits = (data[2] & MAX_BITS_MASK) as usize;
+    if !(MIN_BITS..=MAX_BITS).contains(&max_bits) {
+        return Err(DecodeError::InvalidHeader("code width must be from 9 to 16 bits"));
+    }
+    let first_code = if is_block_mode { CLEAR_CODE + 1 } else { CLEAR_CODE };
+
+    let bin_data = bytes_to_bin(&data[LZW_HEADER_SIZE..]);
+    let mut read_index = 0;
+    let mut group_start = 0;
+    let mut bits = MIN_BITS;
+
+    // every code is a previous code followed by one byte
+    let mut prefixes = vec![0usize; 1 << max_bits];
+    let mut suffixes = (0..1usize << max_bits).map(|x| x as u8).collect::<Vec<u8>>();
+    let mut free_code = first_code;
+    let mut previous: Option<usize> = None;
+
+    let mut answer = Vec::new();
+    let mut sequence = Vec::new();
+    loop {
+        if free_code > max_code(bits, max_bits) {
+            read_index = group_end(read_index, group_start, bits);
+            group_start = read_index;
+            bits += 1;
+        }
+        let Some(code) = read_code(&bin_data, &mut read_index, bits) else {
+            break;
+        };
+
+        let Some(previous_code) = previous else {
+            if code >= CLEAR_CODE {
+                return Err(DecodeError::InvalidSymbol(code as i32));
+            }
+            answer.push(code as u8);
+            previous = Some(code);
+            continue;
+        };
+
+        if is_block_mode && code == CLEAR_CODE {
+            read_index = group_end(read_index, group_start, bits);
+            group_start = read_index;
+            bits = MIN_BITS;
+            free_code = first_code - 1;
+            continue;
+        }
+        // with 9-bit codes a full table still switches to 10-bit codes, which can not be valid
+        if code > free_code || code >= 1 << max_bits {
+            return Err(DecodeError::InvalidSymbol(code as i32));
+        }
+
+        sequence.clear();
+        let mut current = code;
+        if code == free_code {
+            // the code is being defined right now, it starts with the same byte as the previous one
+            current = previous_code;
+        }
+        while current >= CLEAR_CODE {
+            let (Some(&suffix), Some(&prefix)) = (suffixes.get(current), prefixes.get(current)) else {
+                return Err(DecodeError::InvalidSymbol(current as i32));
+            };
+            sequence.push(suffix);
+            current = prefix;
+        }
+        sequence.push(current as u8);
+        let first_byte = current as u8;
+        sequence.reverse();
+        answer.extend_from_slice(&sequence);
+        if code == free_code {
+            answer.push(first_byte);
+        }
+
+        if free_code < 1 << max_bits {
+            prefixes[free_code] = previous_code;
+            suffixes[free_code] = first_byte;
+            free_code += 1;
+        }
+        previous = Some(code);
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_outside_of_full_9_bit_table() {
+        // 256 9-bit codes fill the table, after them come the 10-bit codes 512 and 512
+        let mut bin_data = Vec::new();
+        for code in 0..256 {
+            bin_data.append(&mut to_const_size_lsb_bin(code, 9));
+        }
+        for _ in 0..2 {
+            bin_data.append(&mut to_const_size_lsb_bin(512, 10));
+        }
+        let mut data = vec![LZW_MAGIC[0], LZW_MAGIC[1], FLAG_BLOCK_MODE | 9];
+        data.extend(bin_to_bytes(&bin_data));
+
+        assert_eq!(lzw_decoding(&data), Err(DecodeError::InvalidSymbol(512)));
+    }
+}
@@ -1,730 +1,615 @@
-extern crate core;
-
 use std::cmp::min;
-use std::cmp::Ordering::{Equal};
-use std::collections::HashMap;
-use std::fmt::{Debug};
 use std::fs;
-use std::fs::{File};
+use std::fs::{File, OpenOptions};
+
+use std::io::{BufWriter, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use dzip::armor::{armor_decoding, armor_encoding, is_armored};
+use dzip::bgzf::{bgzf_encoding, is_bgzf, BgzfReader};
+use dzip::dzip_format::{dzip_framed_encoding, is_seekable_dzip, SeekableDzipReader, DEFAULT_FRAME_SIZE};
+use dzip::gzip::{gzip_decoding_with_trailing_size, gzip_encoding, gzip_recompression, is_gzip, GzipHeader, OS_UNIX};
+use dzip::lzw::lzw_encoding;
+use dzip::png::png_recompression;
+use dzip::scan::scan_streams;
+use dzip::sfx::{read_sfx_payload_bounds, SfxWriter};
+use dzip::zip::{CentralDirectoryRecord, ZipEntry, ZipReader, ZipWriter};
+use dzip::zlib::{is_zlib, zlib_encoding, zlib_recompression};
+use dzip::{decompress_data, raw_deflate_encoding, DecodeError, DeflateVariant, MAX_DICTIONARY_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Dzip,
+    Gzip,
+    Zlib,
+    Bgzf,
+    Compress,
+    Deflate,
+}
 
-use std::io::{BufWriter, Read, Write};
+impl Format {
+    fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "dzip" => Some(Format::Dzip),
+            "gzip" => Some(Format::Gzip),
+            "zlib" => Some(Format::Zlib),
+            "bgzf" => Some(Format::Bgzf),
+            "compress" => Some(Format::Compress),
+            "deflate" => Some(Format::Deflate),
+            _ => None
+        }
+    }
 
-use crate::bin_num::{bin_to_num, to_const_size_bin};
-use crate::compared_element::ComparedElement;
-use crate::DeflateElementType::{EndBlock, JustElement, LengthMatch, LengthMatchWithAdd,
-                                LengthMatchWithBinAdd, LengthMatchWithFifthAdd, LengthMatchWithFourthAdd,
-                                LengthMatchWithThirdAdd, MaxMatchLength};
-use crate::huffman::{bounded_huffman, huffman_lengths_to_bin_code};
-use crate::lz77::{encoding_lz77, Lz77Element};
-use crate::lz77::Lz77Element::{ReferenceValue, SimpleValue};
-use crate::lz77::MAX_COINCIDENCE_SIZE;
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Dzip => ".dzip",
+            Format::Gzip | Format::Bgzf => ".gz",
+            Format::Zlib => ".zlib",
+            Format::Compress => ".Z",
+            Format::Deflate => ".deflate",
+        }
+    }
+}
 
-mod lz77;
-mod huffman;
-mod compared_element;
-mod bin_num;
+fn read_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
+    let mut file = File::open(path.as_ref()).expect("no file found");
+    let metadata = fs::metadata(path.as_ref()).expect("unable to read metadata");
+    let mut data = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut data).expect("buffer overflow");
 
-const SEQUENCE_LENGTH_COMMAND: [i32; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    data
+}
 
+fn write_file<P: AsRef<Path>>(path: P, data: &[u8]) {
+    let mut writer = BufWriter::new(File::create(path).unwrap());
 
-#[derive(Debug, Clone)]
-struct DeflateOffset {
-    main_value: u8,
-    additional_bits: u16,
+    writer.write_all(data).expect("Write operation error");
+    writer.flush().expect("Flush operation error");
 }
 
-impl DeflateOffset {
-    fn new(offset: u16) -> DeflateOffset {
-        if offset <= 4 {
-            return DeflateOffset {
-                main_value: offset as u8 - 1,
-                additional_bits: 0,
-            };
+/// Creates a file next to `path` that did not exist before, named after it.
+fn create_temporary_file(path: &Path) -> std::io::Result<(PathBuf, File)> {
+    let name = path.file_name().map(|x| x.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut attempt = 0;
+    loop {
+        let temporary_path = path.with_file_name(format!(".{}.{}.{}.tmp", name, std::process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temporary_path) {
+            Ok(file) => return Ok((temporary_path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e),
         }
-        let base_degree = ((offset - 1) as f32).log2() as u8;
-        let base_num = u16::pow(2, base_degree as u32) + 1;
+    }
+}
 
-        let delta;
-        let mid = base_num + (base_num >> 1) - 1;
+/// Writes a temporary file next to the original and renames it over, so that a failed write
+/// leaves the original intact. The new file gets the permissions of the original, the
+/// temporary file is removed if anything fails.
+fn replace_file(path: &str, data: &[u8]) -> std::io::Result<()> {
+    let permissions = fs::metadata(path)?.permissions();
+    let (temporary_path, file) = create_temporary_file(Path::new(path))?;
 
-        let main_value = if offset <= mid {
-            delta = offset - base_num;
-            base_degree << 1
-        } else {
-            delta = offset - mid - 1;
-            (base_degree << 1) + 1
-        };
-
-        DeflateOffset {
-            main_value,
-            additional_bits: delta,
-        }
+    let result = write_temporary_file(file, data, permissions).and_then(|_| fs::rename(&temporary_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
     }
+    result
+}
 
-    fn get_base_offset(&self) -> u16 {
-        if self.main_value < 4 {
-            return (self.main_value + 1) as u16;
-        }
-
-        let base_num = u16::pow(2, (self.main_value >> 1) as u32) + 1;
-        if (self.main_value & 1) == 0 {
-            return base_num;
-        }
+fn write_temporary_file(file: File, data: &[u8], permissions: fs::Permissions) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    writer.write_all(data)?;
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.set_permissions(permissions)
+}
 
-        base_num + (base_num >> 1)
-    }
+const ARMOR_EXTENSION: &str = ".asc";
+const SFX_STUB_NAME: &str = "dzip-sfx";
 
-    fn get_number_additional_bits(&self) -> u8 {
-        if self.main_value <= 3 {
-            return 0;
+fn decompressed_path(path: &str) -> String {
+    let path = path.strip_suffix(ARMOR_EXTENSION).unwrap_or(path);
+    for format in [Format::Dzip, Format::Gzip, Format::Zlib, Format::Compress, Format::Deflate] {
+        if let Some(stem) = path.strip_suffix(format.extension()) {
+            return format!("{}(1)", stem);
         }
-
-        (self.main_value >> 1) - 1
     }
+    format!("{}(1)", path)
 }
 
-
-#[derive(Clone)]
-enum DeflateLength {
-    SimpleLength(u8),
-    RetryPrevious(u8),
-    RetryZero(u8),
-    BigRetryZero(u8),
+fn modification_time(metadata: &fs::Metadata) -> u32 {
+    metadata.modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_secs() as u32)
+        .unwrap_or(0)
 }
 
-#[derive(Clone)]
-enum DeflateElementType {
-    JustElement(u8),
-    EndBlock,
-    LengthMatch(u16),
-    LengthMatchWithAdd(u16, u8),
-    LengthMatchWithBinAdd(u16, u8),
-    LengthMatchWithThirdAdd(u16, u8),
-    LengthMatchWithFourthAdd(u16, u8),
-    LengthMatchWithFifthAdd(u16, u8),
-    MaxMatchLength,
+#[cfg(unix)]
+fn unix_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
 }
 
-impl DeflateElementType {
-    fn get_number_additional_bits(&self) -> u8 {
-        match self {
-            JustElement(_) => { 0 }
-            EndBlock => { 0 }
-            LengthMatch(_) => { 0 }
-            LengthMatchWithAdd(_, _) => { 1 }
-            LengthMatchWithBinAdd(_, _) => { 2 }
-            LengthMatchWithThirdAdd(_, _) => { 3 }
-            LengthMatchWithFourthAdd(_, _) => { 4 }
-            LengthMatchWithFifthAdd(_, _) => { 5 }
-            MaxMatchLength => { 0 }
-        }
-    }
+#[cfg(not(unix))]
+fn unix_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() { 0o040755 } else { 0o100644 }
+}
 
-    fn get_additional_bits(&self) -> u8 {
-        match self {
-            JustElement(_) => panic!("JustElement not have additional bits"),
-            EndBlock => panic!("EndBlock not have additional bits"),
-            LengthMatch(_) => panic!("LengthMatch not have additional bits"),
-            LengthMatchWithAdd(_, add) => *add,
-            LengthMatchWithBinAdd(_, add) => *add,
-            LengthMatchWithThirdAdd(_, add) => *add,
-            LengthMatchWithFourthAdd(_, add) => *add,
-            LengthMatchWithFifthAdd(_, add) => *add,
-            MaxMatchLength => panic!("MaxMatchLength not have additional bits")
-        }
+fn gzip_header_for(path: &str) -> GzipHeader {
+    GzipHeader {
+        mtime: fs::metadata(path).map(|x| modification_time(&x)).unwrap_or(0),
+        extra: None,
+        name: Path::new(path).file_name().map(|x| x.to_string_lossy().as_bytes().to_vec()),
+        comment: None,
+        os: OS_UNIX,
     }
 }
 
-fn deflate_len_to_compared(elem: DeflateLength) -> ComparedElement<DeflateLength> {
-    match elem {
-        DeflateLength::SimpleLength(v) => { ComparedElement::new(elem, v as i32) }
-        DeflateLength::RetryPrevious(_) => { ComparedElement::new(elem, 16) }
-        DeflateLength::RetryZero(_) => { ComparedElement::new(elem, 17) }
-        DeflateLength::BigRetryZero(_) => { ComparedElement::new(elem, 18) }
-    }
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|x| match x {
+            Component::Normal(v) => Some(v.to_string_lossy().into_owned()),
+            _ => None
+        })
+        .collect::<Vec<String>>()
+        .join("/")
 }
 
-fn lz77_elem_to_compared_deflate_elem_type(elem: &Lz77Element) -> ComparedElement<DeflateElementType> {
-    match elem {
-        SimpleValue(v) => {
-            ComparedElement::new(JustElement(*v), *v as i32)
+/// The data of the files is read only when the archive is written.
+fn collect_zip_entries(path: &Path, entries: &mut Vec<(PathBuf, ZipEntry)>) {
+    let metadata = fs::metadata(path).expect("unable to read metadata");
+    let name = archive_name(path);
+
+    if metadata.is_dir() {
+        if !name.is_empty() {
+            entries.push((path.to_path_buf(), ZipEntry {
+                name: format!("{}/", name),
+                size: 0,
+                mtime: modification_time(&metadata),
+                mode: unix_mode(&metadata),
+                is_directory: true,
+            }));
         }
-        ReferenceValue(v) => {
-            match v.count {
-                c @ 3..=10 => ComparedElement::new(LengthMatch(c + 254), (c + 254) as i32),
-                c @ 11..=18 => {
-                    ComparedElement::new(LengthMatchWithAdd((c - 1) / 2 + 260, ((c - 1) & 1) as u8),
-                                         ((c - 1) / 2 + 260) as i32)
-                }
-                c @ 19..=34 => {
-                    ComparedElement::new(LengthMatchWithBinAdd((c + 1) / 4 + 264, ((c + 1) % 4) as u8),
-                                         ((c + 1) / 4 + 264) as i32)
-                }
-                c @ 35..=66 => {
-                    ComparedElement::new(LengthMatchWithThirdAdd((c + 5) / 8 + 268, ((c + 5) % 8) as u8),
-                                         ((c + 5) / 8 + 268) as i32)
-                }
-                c @ 67..=130 => {
-                    ComparedElement::new(LengthMatchWithFourthAdd((c - 3) / 16 + 273, ((c - 3) % 16) as u8),
-                                         ((c - 3) / 16 + 273) as i32)
-                }
-                c @ 131..=257 => {
-                    ComparedElement::new(LengthMatchWithFifthAdd((c - 3) / 32 + 277, ((c - 3) % 32) as u8),
-                                         ((c - 3) / 32 + 277) as i32)
-                }
-                258 => ComparedElement::new(MaxMatchLength, 285),
-                _ => panic!("length reference value > 258 or < 3")
-            }
+
+        let mut children = fs::read_dir(path)
+            .expect("unable to read directory")
+            .map(|x| x.expect("unable to read directory").path())
+            .collect::<Vec<PathBuf>>();
+        children.sort();
+
+        for child in children {
+            collect_zip_entries(&child, entries);
         }
+    } else {
+        entries.push((path.to_path_buf(), ZipEntry {
+            name,
+            size: metadata.len(),
+            mtime: modification_time(&metadata),
+            mode: unix_mode(&metadata),
+            is_directory: false,
+        }));
     }
 }
 
-fn to_compared_deflate_element_type(id: i32, add: u8) -> ComparedElement<DeflateElementType> {
-    match id {
-        0..=255 => ComparedElement::new(JustElement(id as u8), id),
-        256 => ComparedElement::new(EndBlock, id as i32),
-        257..=264 => ComparedElement::new(LengthMatch(id as u16), id),
-        265..=268 => ComparedElement::new(LengthMatchWithAdd(id as u16, add), id),
-        269..=272 => ComparedElement::new(LengthMatchWithBinAdd(id as u16, add), id),
-        273..=276 => ComparedElement::new(LengthMatchWithThirdAdd(id as u16, add), id),
-        277..=280 => ComparedElement::new(LengthMatchWithFourthAdd(id as u16, add), id),
-        281..=284 => ComparedElement::new(LengthMatchWithFifthAdd(id as u16, add), id),
-        285 => ComparedElement::new(MaxMatchLength, id as i32),
-        _ => panic!("encoded code contains mistake")
+fn write_zip<W: Write>(writer: W, entries: &[(PathBuf, ZipEntry)], variant: DeflateVariant) -> std::io::Result<W> {
+    let mut zip = ZipWriter::new(writer, variant);
+    for (path, entry) in entries {
+        if entry.is_directory {
+            zip.add_entry(entry, std::io::empty())?;
+        } else {
+            zip.add_entry(entry, File::open(path)?)?;
+        }
     }
-}
 
-fn get_deflate_copy_length(elem: &DeflateElementType) -> u16 {
-    match elem {
-        JustElement(_) => panic!("JustElement not have copy length"),
-        EndBlock => panic!("EndBlock not have copy length"),
-        LengthMatch(l) => { l - 254 }
-        LengthMatchWithAdd(l, add) => { ((l - 265) << 1) + 11 + *add as u16 }
-        LengthMatchWithBinAdd(l, add) => { ((l - 269) << 2) + 19 + *add as u16 }
-        LengthMatchWithThirdAdd(l, add) => { ((l - 273) << 3) + 35 + *add as u16 }
-        LengthMatchWithFourthAdd(l, add) => { ((l - 277) << 4) + 67 + *add as u16 }
-        LengthMatchWithFifthAdd(l, add) => { ((l - 281) << 5) + 131 + *add as u16 }
-        MaxMatchLength => { MAX_COINCIDENCE_SIZE }
-    }
+    zip.finish()
 }
 
+fn zip_command(args: &[String]) {
+    let (variant, args) = match args.first().map(|x| x.as_str()) {
+        Some("--deflate64") => (DeflateVariant::Deflate64, &args[1..]),
+        _ => (DeflateVariant::Deflate, args),
+    };
 
-fn encoding_sequence_length(sequence_of_length: &[i32]) -> Vec<DeflateLength> {
-    let mut answer = Vec::new();
+    if args.len() < 2 {
+        println!("Incorrect data");
+        return;
+    }
 
-    let mut number_of_zeros = 0;
+    let mut entries = Vec::new();
+    for input in &args[1..] {
+        collect_zip_entries(Path::new(input), &mut entries);
+    }
 
-    let mut i = 0;
-    while i < sequence_of_length.len() {
-        if sequence_of_length[i] == 0 {
-            number_of_zeros += 1;
-        }
-        if sequence_of_length[i] != 0 {
-            while number_of_zeros >= 3 {
-                if number_of_zeros > 11 {
-                    answer.push(DeflateLength::BigRetryZero(min(138, number_of_zeros)));
-                    number_of_zeros -= min(138, number_of_zeros);
-                } else {
-                    answer.push(DeflateLength::RetryZero(min(10, number_of_zeros)));
-                    number_of_zeros -= min(10, number_of_zeros);
-                }
-            }
-            while number_of_zeros != 0 {
-                answer.push(DeflateLength::SimpleLength(0));
-                number_of_zeros -= 1;
-            }
+    let writer = BufWriter::new(File::create(args[0].as_str()).unwrap());
+    write_zip(writer, &entries, variant).expect("Write operation error");
+    println!("Successful");
+}
 
-            if i > 0 && sequence_of_length[i] == sequence_of_length[i - 1] {
-                let mut count = 1;
-                while i + 1 < sequence_of_length.len() &&
-                    count < 6 && sequence_of_length[i] == sequence_of_length[i + 1] {
-                    count += 1;
-                    i += 1;
-                }
-                if count >= 3 {
-                    answer.push(DeflateLength::RetryPrevious(count));
-                } else {
-                    for _ in 0..count {
-                        answer.push(DeflateLength::SimpleLength(sequence_of_length[i] as u8));
-                    }
+fn png_command(args: &[String]) {
+    if args.is_empty() {
+        println!("Incorrect data");
+        return;
+    }
+
+    let mut total_saved = 0;
+    for path in args {
+        let data = read_file(path);
+        match png_recompression(&data) {
+            Ok(result) if result.len() < data.len() => match replace_file(path, &result) {
+                Ok(()) => {
+                    println!("{}: saved {} bytes", path, data.len() - result.len());
+                    total_saved += data.len() - result.len();
                 }
-            } else {
-                answer.push(DeflateLength::SimpleLength(sequence_of_length[i] as u8));
-            }
+                Err(e) => println!("{}: unable to replace the file: {}", path, e),
+            },
+            Ok(_) => println!("{}: saved 0 bytes", path),
+            Err(e) => println!("{}: unable to recompress: {}", path, e),
         }
-        i += 1;
     }
+    if args.len() > 1 {
+        println!("Total: saved {} bytes", total_saved);
+    }
+}
 
-    answer
+fn scan_command(args: &[String]) {
+    let (is_extraction, path) = match args {
+        [flag, path] if flag == "-x" => (true, path),
+        [path] => (false, path),
+        _ => {
+            println!("Incorrect data");
+            return;
+        }
+    };
+
+    let data = read_file(path);
+    println!("{:>10} {:>8} {:>12} {:>12}", "offset", "kind", "compressed", "decompressed");
+    for stream in scan_streams(&data) {
+        println!("0x{:08x} {:>8} {:>12} {:>12}", stream.offset, stream.kind.name(), stream.compressed_size,
+                 stream.data.len());
+        if is_extraction {
+            write_file(format!("{}_{:08x}.bin", path, stream.offset), &stream.data);
+        }
+    }
 }
 
-fn bin_write_on_file(value: Vec<bool>, path: &str) {
-    let mut writer = BufWriter::new(File::create(path).unwrap());
+fn recompress_command(args: &[String]) {
+    let (dictionary, files) = match args.first().map(|x| x.as_str()) {
+        Some("--dictionary") if args.len() > 1 => (Some(read_file(args[1].as_str())), &args[2..]),
+        _ => (None, args),
+    };
 
-    let mut start = 0;
-    while start < value.len() {
-        let mut element: [u8; 1] = [0];
+    if files.is_empty() {
+        println!("Incorrect data");
+        return;
+    }
 
-        element[0] = value.iter().skip(start).take(8).fold(0, |result, &bit| {
-            (result << 1) ^ if bit { 1 } else { 0 }
-        });
+    for path in files {
+        let data = read_file(path);
+        let result = if is_bgzf(&data) {
+            // the indexes of BGZF files point at the compressed blocks
+            println!("{}: BGZF, kept as is", path);
+            continue;
+        } else if is_gzip(&data) {
+            gzip_recompression(&data)
+        } else if is_zlib(&data) {
+            zlib_recompression(&data, dictionary.as_deref())
+        } else {
+            println!("{}: not a gzip or zlib file", path);
+            continue;
+        };
 
-        writer.write(&element).expect("Write operation error");
-        start += 8;
+        match result {
+            Ok(result) if result.len() < data.len() => match replace_file(path, &result) {
+                Ok(()) => println!("{}: saved {} bytes", path, data.len() - result.len()),
+                Err(e) => println!("{}: unable to replace the file: {}", path, e),
+            },
+            Ok(_) => println!("{}: already smaller than the recompressed data, kept as is", path),
+            Err(e) => println!("{}: unable to recompress: {}", path, e),
+        }
     }
 }
 
-fn sort_huffman_lengths<T>(data: &mut Vec<(ComparedElement<T>, i32)>) {
-    data.sort_by(|f, s| {
-        let mut res = f.1.cmp(&s.1);
-        if res == Equal {
-            res = f.0.sorting_value.cmp(&s.0.sorting_value);
-        }
-        res
-    });
+fn list_zip_records(records: &[CentralDirectoryRecord]) {
+    println!("{:>10}  {:<16}  Name", "Length", "Date       Time");
+    for record in records {
+        let (year, month, day, hour, minute) = record.date_time();
+        println!("{:>10}  {:04}-{:02}-{:02} {:02}:{:02}  {}", record.size, year, month, day, hour, minute, record.name);
+    }
+    let total_size = records.iter().map(|x| x.size).sum::<u64>();
+    println!("{:>10}  {:<16}  {} files", total_size, "", records.len());
 }
 
-
-fn huffman_encoding<T: Clone>(max_depth: i32, lang_size: usize, data: &[ComparedElement<T>]) ->
-(HashMap<ComparedElement<T>, Vec<bool>>,
- Vec<i32>) {
-    let mut lang = Vec::new();
-    let mut repetition_counter = vec![0; lang_size];
-    for val in data {
-        repetition_counter[val.sorting_value as usize] += 1;
-
-        if repetition_counter[val.sorting_value as usize] == 1 {
-            lang.push(val);
+fn unzip_command(args: &[String]) {
+    let (is_listing, path) = match args {
+        [flag, path] if flag == "-l" => (true, path),
+        [path] => (false, path),
+        _ => {
+            println!("Incorrect data");
+            return;
         }
+    };
+
+    let mut file = File::open(path).expect("no file found");
+    let size = fs::metadata(path).expect("unable to read metadata").len();
+    let (start, end) = read_sfx_payload_bounds(&mut file).unwrap_or((0, size));
+    let mut reader = ZipReader::new(file, start, end);
+    let records = match reader.read_central_directory() {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Unable to read archive: {}", e);
+            return;
+        }
+    };
+
+    if is_listing {
+        list_zip_records(&records);
+        return;
     }
 
-    let required_data = repetition_counter
-        .iter()
-        .filter_map(|x| if *x == 0 { None } else { Some(*x) })
-        .collect::<Vec<i32>>();
+    if extract_zip(&mut reader, &records, Path::new(".")) {
+        println!("Successful");
+    }
+}
 
-    let required_lengths = bounded_huffman(max_depth, required_data.as_slice());
+/// Returns false if any of the entries was not extracted, the others are extracted anyway.
+fn extract_zip<R: Read + Seek>(reader: &mut ZipReader<R>, records: &[CentralDirectoryRecord], destination: &Path)
+                               -> bool {
+    let mut is_successful = true;
+    for record in records {
+        if let Err(e) = reader.extract_entry(record, destination) {
+            println!("Unable to extract {}: {}", record.name, e);
+            is_successful = false;
+        }
+    }
 
-    let mut i = 0;
-    let all_lengths = repetition_counter
-        .iter()
-        .map(|x| {
-            if *x == 0 {
-                0
-            } else {
-                i += 1;
-                required_lengths[i - 1]
-            }
-        })
-        .collect::<Vec<i32>>();
+    is_successful
+}
 
-    let mut repetition_element = Vec::new();
-    for val in lang {
-        let sv = val.sorting_value;
-        repetition_element.push((val.clone(), all_lengths[sv as usize]))
+/// The stub is the `dzip-sfx` executable installed next to `dzip`, which only extracts the
+/// zip payload appended to it.
+fn sfx_command(args: &[String]) {
+    if args.len() < 2 {
+        println!("Incorrect data");
+        return;
     }
 
-    sort_huffman_lengths(&mut repetition_element);
-    let bin_codes = huffman_lengths_to_bin_code(repetition_element).0;
+    let mut entries = Vec::new();
+    for input in &args[1..] {
+        collect_zip_entries(Path::new(input), &mut entries);
+    }
 
-    (bin_codes, all_lengths)
+    let stub_path = std::env::current_exe().expect("unable to find the executable")
+        .with_file_name(format!("{}{}", SFX_STUB_NAME, std::env::consts::EXE_SUFFIX));
+    let stub = match File::open(&stub_path) {
+        Ok(v) => v,
+        Err(e) => {
+            println!("Unable to open the stub {}: {}", stub_path.display(), e);
+            return;
+        }
+    };
+    let permissions = stub.metadata().expect("unable to read metadata").permissions();
+    let writer = BufWriter::new(File::create(args[0].as_str()).unwrap());
+    SfxWriter::new(writer, stub)
+        .and_then(|x| write_zip(x, &entries, DeflateVariant::Deflate))
+        .and_then(|x| x.finish())
+        .expect("Write operation error");
+    // the archive runs like its stub
+    fs::set_permissions(args[0].as_str(), permissions).expect("unable to set permissions");
+    println!("Successful");
 }
 
-fn deflate_block_decoding(answer: &mut Vec<u8>, data: &[bool]) -> (usize, bool) {
-
-    let hlit = bin_to_num(&data[3..8]);
-    let hdist = bin_to_num(&data[8..13]);
-    let hclen = bin_to_num(&data[13..17]) as usize;
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let (start, end) = range.split_once("..")?;
+    let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
 
-    if data[1] || !data[2] {
-        panic!("not supported");
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
     }
+}
 
-    let mut deflate_len_elements = Vec::new();
+/// Only the frames overlapping the range are decoded when the file is seekable,
+/// any other file is decompressed as a whole.
+fn range_decoding(path: &str, (start, end): (u64, u64), dictionary: Option<&[u8]>, variant: DeflateVariant)
+                  -> Result<Vec<u8>, DecodeError> {
+    let file = File::open(path).expect("no file found");
+    let mut header = Vec::new();
+    (&file).take(8).read_to_end(&mut header)?;
+
+    if is_seekable_dzip(&header) {
+        let mut reader = SeekableDzipReader::new(file, dictionary)?;
+        return reader.read_at(start, (end - start) as usize);
+    }
 
-    let mut read_index = 17;
-    for sequence_index in 0..hclen + 4 {
-        let index = SEQUENCE_LENGTH_COMMAND[sequence_index] as usize;
-        let num = bin_to_num(&data[read_index..read_index + 3]);
+    let answer = decompress_data(read_file(path), dictionary, variant)?;
+    let start = min(start, answer.len() as u64) as usize;
+    let end = min(end, answer.len() as u64) as usize;
+    Ok(answer[start..end].to_vec())
+}
 
-        if num != 0 {
-            deflate_len_elements.push((match index {
-                0..=15 => ComparedElement::new(
-                    DeflateLength::SimpleLength(index as u8),
-                    index as i32),
+fn virtual_offset_decoding(path: &str, virtual_offset: u64) -> Result<Vec<u8>, DecodeError> {
+    let mut reader = BgzfReader::new(File::open(path).expect("no file found"));
+    reader.seek_virtual(virtual_offset)?;
 
-                16 => ComparedElement::new(DeflateLength::RetryPrevious(0), 16),
-                17 => ComparedElement::new(DeflateLength::RetryZero(0), 17),
-                18 => ComparedElement::new(DeflateLength::BigRetryZero(0), 18),
-                _ => panic!("encoded code contains mistake")
-            }, num));
-        }
-        read_index += 3;
-    }
+    let mut answer = Vec::new();
+    reader.read_to_end(&mut answer).map_err(|e| match e.into_inner().map(|x| x.downcast::<DecodeError>()) {
+        Some(Ok(e)) => *e,
+        _ => DecodeError::Io(std::io::ErrorKind::InvalidData),
+    })?;
 
-    sort_huffman_lengths(&mut deflate_len_elements);
-    let lang_len_elements = huffman_lengths_to_bin_code(deflate_len_elements).1;
+    Ok(answer)
+}
 
-    let mut deflate_elements = Vec::new();
+fn compression_command(args: &[String]) {
+    let mut is_decompression = false;
+    let mut is_seekable = false;
+    let mut has_checksums = false;
+    let mut is_armored_output = false;
+    let mut format = Format::Dzip;
+    let mut variant = DeflateVariant::Deflate;
+    let mut dictionary = None;
+    let mut range = None;
+    let mut virtual_offset = None;
+    let mut files = Vec::new();
 
-    let mut temp = Vec::new();
-    let mut i: usize = 0;
-    while i < (hlit + 257) as usize {
-        temp.push(data[read_index]);
-        read_index += 1;
-        if let Some(elem) = lang_len_elements.get(&temp) {
-            match elem.value {
-                DeflateLength::SimpleLength(v) => {
-                    if v != 0 {
-                        deflate_elements.push((to_compared_deflate_element_type(i as i32, 0), v as i32));
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-d" => is_decompression = true,
+            "--deflate64" => variant = DeflateVariant::Deflate64,
+            "--seekable" => is_seekable = true,
+            "--checksums" => has_checksums = true,
+            "--armor" => is_armored_output = true,
+            "--range" => {
+                i += 1;
+                match args.get(i).and_then(|x| parse_range(x)) {
+                    Some(v) => range = Some(v),
+                    None => {
+                        println!("Incorrect data");
+                        return;
                     }
-                    i += 1;
                 }
-                DeflateLength::RetryPrevious(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 2]) + 3;
-                    let length_previous_code = deflate_elements.last().unwrap().1;
-                    for _ in 0..count {
-                        deflate_elements.push((to_compared_deflate_element_type(i as i32, 0),
-                                               length_previous_code));
-                        i += 1;
+            }
+            "--virtual-offset" => {
+                i += 1;
+                match args.get(i).and_then(|x| x.parse::<u64>().ok()) {
+                    Some(v) => virtual_offset = Some(v),
+                    None => {
+                        println!("Incorrect data");
+                        return;
                     }
-                    read_index += 2;
-                }
-                DeflateLength::RetryZero(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 3]) + 3;
-                    i += count as usize;
-                    read_index += 3;
-                }
-                DeflateLength::BigRetryZero(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 7]) + 11;
-                    i += count as usize;
-                    read_index += 7;
                 }
             }
-            temp.clear();
-        }
-    }
-
-    sort_huffman_lengths(&mut deflate_elements);
-    let lang_deflate_elements = huffman_lengths_to_bin_code(deflate_elements).1;
-
-    let mut deflate_offset_elements = Vec::new();
-
-    let mut temp = Vec::new();
-    let mut i: usize = 0;
-    while i < hdist as usize {
-        temp.push(data[read_index]);
-        read_index += 1;
-        if let Some(elem) = lang_len_elements.get(&temp) {
-            match elem.value {
-                DeflateLength::SimpleLength(v) => {
-                    if v != 0 {
-                        deflate_offset_elements.push((ComparedElement::new(DeflateOffset {
-                            main_value: i as u8,
-                            additional_bits: 0,
-                        }, i as i32), v as i32));
+            "--format" => {
+                i += 1;
+                match args.get(i).and_then(|x| Format::from_name(x)) {
+                    Some(v) => format = v,
+                    None => {
+                        println!("Incorrect data");
+                        return;
                     }
-                    i += 1;
                 }
-                DeflateLength::RetryPrevious(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 2]) + 3;
-                    let length_previous_code = deflate_offset_elements.last().unwrap().1;
-
-                    for _ in 0..count {
-                        deflate_offset_elements.push((ComparedElement::new(DeflateOffset {
-                            main_value: i as u8,
-                            additional_bits: 0,
-                        }, i as i32), length_previous_code));
-
-                        i += 1;
+            }
+            "--dictionary" => {
+                i += 1;
+                match args.get(i) {
+                    Some(v) => dictionary = Some(read_file(v)),
+                    None => {
+                        println!("Incorrect data");
+                        return;
                     }
-                    read_index += 2;
-                }
-                DeflateLength::RetryZero(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 3]) + 3;
-                    i += count as usize;
-                    read_index += 3;
-                }
-                DeflateLength::BigRetryZero(_) => {
-                    let count = bin_to_num(&data[read_index..read_index + 7]) + 11;
-                    i += count as usize;
-                    read_index += 7;
                 }
             }
-            temp.clear();
+            _ => files.push(args[i].as_str())
         }
+        i += 1;
     }
 
-    sort_huffman_lengths(&mut deflate_offset_elements);
-    let lang_deflate_offset_elements = huffman_lengths_to_bin_code(deflate_offset_elements).1;
-
-    let mut length_match = 0;
-    let mut finding_offset = false;
-    let mut temp = Vec::new();
-    loop {
-        temp.push(data[read_index]);
-        read_index += 1;
-
-        if finding_offset {
-            if let Some(elem) = lang_deflate_offset_elements.get(&temp) {
-                let base_num = elem.value.get_base_offset();
-
-                let number_additional_bits = elem.value.get_number_additional_bits();
-
-                let offset = base_num + bin_to_num(&data[read_index..read_index + number_additional_bits as usize]) as u16;
-                read_index += number_additional_bits as usize;
-
-                for _ in 0..length_match {
-                    answer.push(answer[answer.len() - offset as usize]);
-                }
-                temp.clear();
-                finding_offset = false;
-            }
-        } else if let Some(elem) = lang_deflate_elements.get(&temp) {
-            match elem.value {
-                JustElement(v) => answer.push(v),
-                EndBlock => break,
-                _ => {
-                    let base_length_match = get_deflate_copy_length(&elem.value) as usize;
-
-                    let number_additional_bits = elem.value.get_number_additional_bits();
-
-                    length_match = base_length_match + bin_to_num(&data[read_index..read_index + number_additional_bits as usize]) as usize;
-                    read_index += number_additional_bits as usize;
-
-                    finding_offset = true;
-                }
-            }
-            temp.clear();
-        }
+    if files.len() != 1 || (variant == DeflateVariant::Deflate64 && !matches!(format, Format::Dzip | Format::Deflate)) ||
+        ((is_seekable || has_checksums) && format != Format::Dzip) ||
+        ((range.is_some() || virtual_offset.is_some()) && !is_decompression) || (is_armored_output && is_decompression) {
+        println!("Incorrect data");
+        return;
     }
-
-    (read_index, data[0] == true)
-}
-
-
-fn deflate_decoding(data: Vec<u8>) -> Vec<u8> {
-    let mut bin_data = Vec::new();
-
-    let mut offset: i32 = -1;
-    for i in 0..data.len() {
-        if i + 1 < data.len() {
-            for chr in format!("{:8b}", data[i]).chars() {
-                bin_data.push(chr == '1');
-            }
-        } else {
-            let bin_value = format!("{:b}", data[i]);
-            for _ in 0..offset as usize - bin_value.len() {
-                bin_data.push(false);
-            }
-            for chr in bin_value.chars() {
-                bin_data.push(chr == '1');
-            }
-        }
-        if offset == -1 && bin_data.len() >= 3 {
-            offset = bin_to_num(&bin_data[0..3]);
-            if offset == 0 {
-                offset = 8;
-            }
-        }
+    if dictionary.as_ref().is_some_and(|x| x.len() > MAX_DICTIONARY_SIZE) {
+        println!("Dictionary must not be larger than {} bytes", MAX_DICTIONARY_SIZE);
+        return;
     }
-    let mut start_block = 3;
-    let mut answer: Vec<u8> = Vec::new();
-
-    loop {
-        let local_data = &bin_data[start_block..];
-        let (shift, is_end_block) = deflate_block_decoding(&mut answer, local_data);
-        start_block += shift;
-
-        if is_end_block {
-            if start_block != bin_data.len() {
-                panic!("Internal error");
-            }
-            break;
-        }
+    if dictionary.is_some() && !matches!(format, Format::Dzip | Format::Zlib) && !is_decompression {
+        println!("Only dzip and zlib support preset dictionaries");
+        return;
     }
-    answer
-}
-
-fn deflate_block_encoding(lz77_data: &[Lz77Element], is_end_block: bool) -> Vec<bool> {
-    let mut bin_result = Vec::new();
-
-    let mut deflate_elements = lz77_data
-        .iter()
-        .map(|x| lz77_elem_to_compared_deflate_elem_type(x))
-        .collect::<Vec<ComparedElement<DeflateElementType>>>();
-    deflate_elements.push(ComparedElement::new(EndBlock, 256));
-
-    let (bin_deflate_codes, all_deflate_lengths) = huffman_encoding(15,
-                                                                    286,
-                                                                    deflate_elements.as_slice());
-
-    let offset_elements = lz77_data
-        .iter()
-        .filter_map(|x| match x {
-            SimpleValue(_) => { None }
-            ReferenceValue(v) => {
-                let offset = DeflateOffset::new(v.offset);
-                let compared_value = offset.main_value;
-                Some(ComparedElement::new(offset, compared_value as i32))
-            }
-        }).collect::<Vec<ComparedElement<DeflateOffset>>>();
-
-    let (bin_offset_codes, all_offset_lengths) = huffman_encoding(15,
-                                                                      30,
-                                                                      offset_elements.as_slice());
+    let dictionary = dictionary.as_deref();
+    let path = files[0];
 
-
-    let mut encoded_sequence_lengths = encoding_sequence_length(
-        all_deflate_lengths.as_slice());
-
-    let encoded_sequence_offset_lengths = encoding_sequence_length(
-        all_offset_lengths.as_slice());
-
-    encoded_sequence_lengths.extend(encoded_sequence_offset_lengths);
-
-    let deflate_length_elements = encoded_sequence_lengths
-        .iter()
-        .map(|x| deflate_len_to_compared(x.clone()))
-        .collect::<Vec<ComparedElement<DeflateLength>>>();
-
-    let (bin_deflate_len_codes, all_deflate_len_lengths) = huffman_encoding(7,
-                                                                            19,
-                                                                            deflate_length_elements.as_slice());
-
-
-    let hlit = bin_deflate_codes.keys().filter_map(|x|
-        match &x.value {
-            JustElement(_) => { None }
-            EndBlock => { None }
-            _ => { Some(x.sorting_value) }
-        })
-        .max().unwrap_or(256) - 256;
-
-    let hdist = bin_offset_codes.keys().map(|v| v.sorting_value).max().unwrap_or(-1) + 1;
-
-    let mut hclen = SEQUENCE_LENGTH_COMMAND.len() - 4;
-    for v in SEQUENCE_LENGTH_COMMAND.iter().rev() {
-        if all_deflate_len_lengths[*v as usize] == 0 {
-            hclen -= 1;
-        } else {
-            break;
+    if let Some(virtual_offset) = virtual_offset {
+        match virtual_offset_decoding(path, virtual_offset) {
+            Ok(result) => write_file(decompressed_path(path).as_str(), &result),
+            Err(e) => println!("Unable to decompress: {}", e)
         }
+        return;
     }
-
-    bin_result.append(&mut vec![is_end_block, false, true]);
-
-    bin_result.append(&mut to_const_size_bin(hlit, 5));
-    bin_result.append(&mut to_const_size_bin(hdist, 5));
-    bin_result.append(&mut to_const_size_bin(hclen as i32, 4));
-
-    for length_index in &SEQUENCE_LENGTH_COMMAND[..hclen + 4] {
-        let mut bin_value = to_const_size_bin(all_deflate_len_lengths[*length_index as usize], 3);
-        bin_result.append(&mut bin_value)
+    if let Some(range) = range {
+        match range_decoding(path, range, dictionary, variant) {
+            Ok(result) => write_file(decompressed_path(path).as_str(), &result),
+            Err(e) => println!("Unable to decompress: {}", e)
+        }
+        return;
     }
 
-    encoded_sequence_lengths.into_iter().for_each(|v| {
-        bin_result.extend(bin_deflate_len_codes.get(&deflate_len_to_compared(v.clone())).unwrap());
-
-        match v {
-            DeflateLength::RetryPrevious(v) => {
-                bin_result.extend(to_const_size_bin(v as i32 - 3, 2))
-            }
-            DeflateLength::RetryZero(v) => {
-                bin_result.extend(to_const_size_bin(v as i32 - 3, 3))
-            }
-            DeflateLength::BigRetryZero(v) => {
-                bin_result.extend(to_const_size_bin(v as i32 - 11, 7))
-            }
-            _ => ()
+    let data = read_file(path);
+    if !is_decompression {
+        let mut output_path = format!("{}{}", path, format.extension());
+        let mut result = match format {
+            Format::Dzip => dzip_framed_encoding(data, dictionary, variant, DEFAULT_FRAME_SIZE, is_seekable,
+                                                 has_checksums),
+            Format::Gzip => gzip_encoding(data, &gzip_header_for(path)),
+            Format::Zlib => zlib_encoding(data, dictionary),
+            Format::Bgzf => bgzf_encoding(data.as_slice()),
+            Format::Compress => lzw_encoding(data.as_slice()),
+            Format::Deflate => raw_deflate_encoding(data, variant),
         };
-    });
-
-    for value in lz77_data {
-        let compared_deflate_elem = lz77_elem_to_compared_deflate_elem_type(&value);
-        bin_result.extend(bin_deflate_codes.get(&compared_deflate_elem).unwrap());
-
-        if let ReferenceValue(v) = value {
-            let number_additional_bits_for_match = compared_deflate_elem.value.get_number_additional_bits() as i32;
-
-            if number_additional_bits_for_match != 0 {
-                bin_result.extend(to_const_size_bin(compared_deflate_elem.value.get_additional_bits() as i32,
-                                                    number_additional_bits_for_match));
-            }
-
-            let offset = DeflateOffset::new(v.offset);
-            let compared_value = offset.main_value;
-            let additional_bits = offset.additional_bits as i32;
-            let number_additional_bits_for_offset = offset.get_number_additional_bits() as i32;
-
-            bin_result.extend(bin_offset_codes.get(&ComparedElement::new(offset, compared_value as i32)).unwrap());
-
-            if number_additional_bits_for_offset != 0 {
-                bin_result.extend(to_const_size_bin(additional_bits, number_additional_bits_for_offset));
-            }
+        if is_armored_output {
+            result = armor_encoding(&result);
+            output_path.push_str(ARMOR_EXTENSION);
         }
-    }
-    bin_result.extend(bin_deflate_codes.get(&ComparedElement::new(EndBlock, 256)).unwrap());
-
-    bin_result
-}
-
-fn deflate_encoding(data: Vec<u8>) -> Vec<bool> {
-    let lz77_result = encoding_lz77(&data);
-
-    let mut bin_result: Vec<bool> = vec![false; 3];
-
-    let step = (i32::pow(2, 16) - 1) as usize;
-    for start_block in (0..lz77_result.len()).step_by(step) {
-        let lz77_local;
-        let mut is_end_block = false;
-
-        if lz77_result.len() < start_block + step {
-            lz77_local = &lz77_result[start_block..lz77_result.len()];
-            is_end_block = true;
+        write_file(output_path.as_str(), &result);
+        println!("Successful");
+    } else {
+        let data = if is_armored(&data) {
+            match armor_decoding(&data) {
+                Ok(v) => v,
+                Err(e) => {
+                    println!("Unable to decompress: {}", e);
+                    return;
+                }
+            }
+        } else {
+            data
+        };
+        let result = if is_gzip(&data) {
+            gzip_decoding_with_trailing_size(&data).map(|(answer, trailing_size)| {
+                if trailing_size != 0 {
+                    println!("{}: decompression OK, trailing garbage ignored", path);
+                }
+                answer
+            })
         } else {
-            lz77_local = &lz77_result[start_block..start_block + step];
+            decompress_data(data, dictionary, variant)
+        };
+        match result {
+            Ok(result) => write_file(decompressed_path(path).as_str(), &result),
+            Err(e) => println!("Unable to decompress: {}", e)
         }
-
-        bin_result.extend(deflate_block_encoding(lz77_local, is_end_block));
-    }
-
-    let bin_size = to_const_size_bin((bin_result.len() % 8) as i32, 3);
-    for i in 0..3 {
-        bin_result[i] = bin_size[i];
     }
-
-    bin_result
 }
 
 fn main() {
     let args = std::env::args().skip(1).collect::<Vec<String>>();
 
-    if args.len() > 2 || args.len() == 0 {
-        println!("Incorrect data");
-    } else {
-        if args.len() == 1 {
-            let mut file = File::open(args[0].as_str()).expect("no file found");
-            let metadata = fs::metadata(args[0].as_str()).expect("unable to read metadata");
-            let mut data = Vec::with_capacity(metadata.len() as usize);
-            file.read_to_end(&mut data).expect("buffer overflow");
-
-            bin_write_on_file(deflate_encoding(data), format!("{}.dzip", args[0]).as_str());
-            println!("Successful");
-        } else if args[0] == "-d" {
-            let mut file = File::open(args[1].as_str()).expect("no file found");
-            let metadata = fs::metadata(args[1].as_str()).expect("unable to read metadata");
-            let mut data = Vec::with_capacity(metadata.len() as usize);
-            file.read_to_end(&mut data).expect("buffer overflow");
-
-            let result = deflate_decoding(data);
-
-            let mut writer = BufWriter::new(File::create(args[1].replace(".dzip", "(1)")).unwrap());
-
-            writer.write(result.as_slice()).expect("Write operation error");
-            writer.flush().expect("Flush operation error");
-        } else {
-            println!("Incorrect data");
-        }
+    match args.first().map(|x| x.as_str()) {
+        Some("zip") => zip_command(&args[1..]),
+        Some("unzip") => unzip_command(&args[1..]),
+        Some("png") => png_command(&args[1..]),
+        Some("recompress") => recompress_command(&args[1..]),
+        Some("sfx") => sfx_command(&args[1..]),
+        Some("scan") => scan_command(&args[1..]),
+        _ => compression_command(&args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_parsed() {
+        assert_eq!(parse_range("10..20"), Some((10, 20)));
+        assert_eq!(parse_range("7..7"), Some((7, 7)));
+        assert_eq!(parse_range("20..10"), None);
+        assert_eq!(parse_range("10"), None);
+        assert_eq!(parse_range("..20"), None);
+        assert_eq!(parse_range("-1..20"), None);
     }
 }
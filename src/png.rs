@@ -0,0 +1,137 @@
+//! Lossless recompression of PNG images: only the zlib stream of the image data changes.
+
+use crate::crc32::crc32;
+use crate::error::DecodeError;
+use crate::zlib::{zlib_decoding, zlib_encoding_with_level};
+use crate::CompressionLevel;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+const CHUNK_IMAGE_DATA: [u8; 4] = *b"IDAT";
+const CHUNK_IMAGE_END: [u8; 4] = *b"IEND";
+const MAX_IMAGE_DATA_CHUNK_SIZE: usize = 1 << 20;
+
+struct PngChunk {
+    pub kind: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Returns true if the data starts with the PNG signature.
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&PNG_SIGNATURE)
+}
+
+fn chunk_crc(kind: &[u8; 4], data: &[u8]) -> u32 {
+    let mut bytes = kind.to_vec();
+    bytes.extend_from_slice(data);
+    crc32(bytes.as_slice())
+}
+
+fn read_png_chunks(data: &[u8]) -> Result<Vec<PngChunk>, DecodeError> {
+    if !is_png(data) {
+        return Err(DecodeError::InvalidHeader("not a png file"));
+    }
+
+    let mut chunks = Vec::new();
+    let mut index = PNG_SIGNATURE.len();
+    loop {
+        let header = data.get(index..index + 8).ok_or(DecodeError::UnexpectedEnd)?;
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        index += 8;
+
+        let chunk_data = data.get(index..index + length).ok_or(DecodeError::UnexpectedEnd)?;
+        index += length;
+        let crc = data.get(index..index + 4).ok_or(DecodeError::UnexpectedEnd)?;
+        index += 4;
+
+        if u32::from_be_bytes(crc.try_into().unwrap()) != chunk_crc(&kind, chunk_data) {
+            return Err(DecodeError::InvalidHeader("chunk checksum does not match"));
+        }
+
+        chunks.push(PngChunk { kind, data: chunk_data.to_vec() });
+        if kind == CHUNK_IMAGE_END {
+            break;
+        }
+    }
+
+    Ok(chunks)
+}
+
+fn write_png_chunk(answer: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    answer.extend((data.len() as u32).to_be_bytes());
+    answer.extend(kind);
+    answer.extend(data);
+    answer.extend(chunk_crc(kind, data).to_be_bytes());
+}
+
+/// Inflates the image data and deflates it again with the best compression level.
+/// All IDAT chunks are merged into consecutive chunks at the place of the first one,
+/// the other chunks are copied as is.
+pub fn png_recompression(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let chunks = read_png_chunks(data)?;
+
+    let image_data = chunks.iter()
+        .filter(|x| x.kind == CHUNK_IMAGE_DATA)
+        .flat_map(|x| x.data.iter().copied())
+        .collect::<Vec<u8>>();
+    if image_data.is_empty() {
+        return Err(DecodeError::InvalidHeader("no image data"));
+    }
+
+    let pixels = zlib_decoding(image_data.as_slice(), None)?;
+    let recompressed = zlib_encoding_with_level(pixels.clone(), None, CompressionLevel::Best);
+    if zlib_decoding(recompressed.as_slice(), None)? != pixels {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    let mut answer = PNG_SIGNATURE.to_vec();
+    let mut is_image_data_written = false;
+    for chunk in &chunks {
+        if chunk.kind != CHUNK_IMAGE_DATA {
+            write_png_chunk(&mut answer, &chunk.kind, chunk.data.as_slice());
+        } else if !is_image_data_written {
+            for part in recompressed.chunks(MAX_IMAGE_DATA_CHUNK_SIZE) {
+                write_png_chunk(&mut answer, &CHUNK_IMAGE_DATA, part);
+            }
+            is_image_data_written = true;
+        }
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zlib::zlib_encoding;
+
+    fn image_data(chunks: &[PngChunk]) -> Vec<u8> {
+        chunks.iter().filter(|x| x.kind == CHUNK_IMAGE_DATA).flat_map(|x| x.data.iter().copied()).collect()
+    }
+
+    #[test]
+    fn pixels_are_kept() {
+        // a 64x64 grayscale image, each row starts with filter type 0
+        let pixels = (0..64).flat_map(|y| [0].into_iter().chain((0..64).map(move |x| (x * y % 7) as u8)))
+            .collect::<Vec<u8>>();
+        let mut header = 64u32.to_be_bytes().repeat(2);
+        header.extend([8, 0, 0, 0, 0]);
+        let compressed = zlib_encoding(pixels.clone(), None);
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &header);
+        assert!(compressed.len() > 40);
+        for part in compressed.chunks(20) {
+            write_png_chunk(&mut png, &CHUNK_IMAGE_DATA, part);
+        }
+        write_png_chunk(&mut png, b"tEXt", b"Comment\0kept after the image data");
+        write_png_chunk(&mut png, &CHUNK_IMAGE_END, &[]);
+
+        let recompressed = read_png_chunks(&png_recompression(&png).unwrap()).unwrap();
+        let kinds = recompressed.iter().map(|x| &x.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, [b"IHDR", &CHUNK_IMAGE_DATA, b"tEXt", &CHUNK_IMAGE_END]);
+        assert_eq!(recompressed[0].data, header);
+        assert_eq!(recompressed[2].data, b"Comment\0kept after the image data");
+        assert_eq!(zlib_decoding(&image_data(&recompressed), None).unwrap(), pixels);
+    }
+}
@@ -0,0 +1,169 @@
+//! Search for compressed streams embedded in other data, such as firmware images or
+//! memory dumps.
+
+use crate::error::DecodeError;
+use crate::gzip::{gzip_member_decoding, is_gzip};
+use crate::zlib::{is_zlib, zlib_stream_decoding};
+use crate::{deflate_stream_decoding, DeflateVariant, MAX_STORED_BLOCK_SIZE};
+
+// most offsets are not streams, so only a small part of the data is decoded at first
+const INITIAL_WINDOW_SIZE: usize = 64;
+// random data passes for a fixed Huffman block now and then, but only for a few dozen bytes
+// before it hits an invalid code or distance
+const MIN_DEFLATE_SIZE: usize = 256;
+// the header of a stored block
+const STORED_BLOCK_HEADER_SIZE: usize = 5;
+const MAX_LITERAL_CODES: u8 = 29;
+const MAX_DISTANCE_CODES: u8 = 29;
+
+/// The container of a found stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StreamKind {
+    /// A gzip member.
+    Gzip,
+    /// A zlib stream.
+    Zlib,
+    /// A bare deflate stream.
+    Deflate,
+}
+
+impl StreamKind {
+    /// The lowercase name of the format.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamKind::Gzip => "gzip",
+            StreamKind::Zlib => "zlib",
+            StreamKind::Deflate => "deflate",
+        }
+    }
+}
+
+/// A stream found in the data.
+#[non_exhaustive]
+pub struct FoundStream {
+    /// Where the stream starts in the data.
+    pub offset: usize,
+    /// The container of the stream.
+    pub kind: StreamKind,
+    /// The size of the stream including its container.
+    pub compressed_size: usize,
+    /// The decompressed data.
+    pub data: Vec<u8>,
+}
+
+/// Decodes a growing prefix of the data while the decoder runs out of it, so that a
+/// candidate that is not a stream fails after looking at a few bytes.
+fn windowed_decoding<F>(data: &[u8], decoding: F) -> Option<(Vec<u8>, usize)>
+    where F: Fn(&[u8]) -> Result<(Vec<u8>, usize), DecodeError> {
+    let mut window = INITIAL_WINDOW_SIZE;
+    loop {
+        let end = window.min(data.len());
+        match decoding(&data[..end]) {
+            Ok(v) => return Some(v),
+            Err(DecodeError::UnexpectedEnd) if end < data.len() => window *= 2,
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Checks the header of the first block before running the decoder.
+fn is_plausible_deflate(data: &[u8]) -> bool {
+    let Some(first) = data.first() else {
+        return false;
+    };
+
+    match (first >> 1) & 0b11 {
+        0 => data.len() >= 5 && first >> 3 == 0 && u16::from_le_bytes([data[1], data[2]]) ==
+            !u16::from_le_bytes([data[3], data[4]]),
+        1 => true,
+        2 => data.len() >= 2 && first >> 3 <= MAX_LITERAL_CODES && data[1] & 0x1f <= MAX_DISTANCE_CODES,
+        _ => false,
+    }
+}
+
+/// An encoder falls back to stored blocks when compression does not pay off, so a stream
+/// larger than the data in stored blocks was not written by one.
+fn is_plausible_deflate_size(compressed_size: usize, size: usize) -> bool {
+    compressed_size <= size + size.div_ceil(MAX_STORED_BLOCK_SIZE).max(1) * STORED_BLOCK_HEADER_SIZE
+}
+
+fn stream_at(data: &[u8], offset: usize) -> Option<FoundStream> {
+    let rest = &data[offset..];
+
+    let (kind, (answer, compressed_size)) = if is_gzip(rest) {
+        (StreamKind::Gzip, windowed_decoding(rest, |x| gzip_member_decoding(x).map(|(_, v, size)| (v, size)))?)
+    } else if is_zlib(rest) {
+        (StreamKind::Zlib, windowed_decoding(rest, |x| zlib_stream_decoding(x, None))?)
+    } else if is_plausible_deflate(rest) {
+        let (answer, size) = windowed_decoding(rest, |x| deflate_stream_decoding(x, DeflateVariant::Deflate))?;
+        if size < MIN_DEFLATE_SIZE || !is_plausible_deflate_size(size, answer.len()) {
+            return None;
+        }
+        (StreamKind::Deflate, (answer, size))
+    } else {
+        return None;
+    };
+
+    Some(FoundStream { offset, kind, compressed_size, data: answer })
+}
+
+/// Tries the decoder at every offset. Streams found do not overlap, the search continues
+/// right after the end of each one.
+pub fn scan_streams(data: &[u8]) -> Vec<FoundStream> {
+    let mut answer = Vec::new();
+
+    let mut offset = 0;
+    while offset < data.len() {
+        match stream_at(data, offset) {
+            Some(stream) => {
+                offset += stream.compressed_size.max(1);
+                answer.push(stream);
+            }
+            None => offset += 1,
+        }
+    }
+
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gzip::{gzip_encoding, GzipHeader};
+    use crate::raw_deflate_encoding;
+    use crate::test_data::random_bytes;
+
+
+    fn text(lines: usize) -> Vec<u8> {
+        (0..lines).flat_map(|x| format!("line {} of {}: {}\n", x, lines, x * x % 97).into_bytes()).collect()
+    }
+
+    #[test]
+    fn random_data_has_no_streams() {
+        let data = random_bytes(1 << 20, 0x2545f491);
+        let found = scan_streams(&data).iter().map(|x| (x.offset, x.kind)).collect::<Vec<_>>();
+        assert_eq!(found, []);
+    }
+
+    #[test]
+    fn embedded_streams_are_found() {
+        let deflate = raw_deflate_encoding(text(500), DeflateVariant::Deflate);
+        let gzip = gzip_encoding(text(20), &GzipHeader::default());
+
+        let mut data = random_bytes(3000, 1);
+        data.extend(&deflate);
+        data.extend(random_bytes(3000, 2));
+        data.extend(&gzip);
+        data.extend(random_bytes(3000, 3));
+
+        let found = scan_streams(&data);
+        let found_kinds = found.iter().map(|x| (x.offset, x.kind, x.compressed_size)).collect::<Vec<_>>();
+        assert_eq!(found_kinds, [
+            (3000, StreamKind::Deflate, deflate.len()),
+            (6000 + deflate.len(), StreamKind::Gzip, gzip.len()),
+        ]);
+        assert_eq!(found[0].data, text(500));
+        assert_eq!(found[1].data, text(20));
+    }
+}
@@ -0,0 +1,119 @@
+//! Self-extracting archives: an executable stub followed by a payload and a trailer that
+//! locates the payload.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::crc32::update_crc32;
+use crate::error::DecodeError;
+
+const SFX_MAGIC: [u8; 8] = *b"DZIPSFX1";
+const SFX_TRAILER_SIZE: usize = 20;
+
+// the payload read at once to check its CRC-32
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Writes a self-extracting archive: the stub executable, then everything written to it as
+/// the payload, then a trailer written by [`SfxWriter::finish`]: the offset of the payload
+/// (8 bytes), the CRC-32 of the payload (4 bytes), all little-endian, and `SFX_MAGIC`.
+pub struct SfxWriter<W: Write> {
+    writer: W,
+    payload_offset: u64,
+    crc: u32,
+}
+
+impl<W: Write> SfxWriter<W> {
+    /// Copies the whole stub into the writer.
+    pub fn new<R: Read>(mut writer: W, mut stub: R) -> std::io::Result<SfxWriter<W>> {
+        let payload_offset = std::io::copy(&mut stub, &mut writer)?;
+        Ok(SfxWriter { writer, payload_offset, crc: 0 })
+    }
+
+    /// Writes the trailer and returns the inner writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.writer.write_all(&self.payload_offset.to_le_bytes())?;
+        self.writer.write_all(&self.crc.to_le_bytes())?;
+        self.writer.write_all(&SFX_MAGIC)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for SfxWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.writer.write(buf)?;
+        self.crc = update_crc32(self.crc, &buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Returns the offset of the payload if the trailer belongs to a self-extracting archive.
+fn read_sfx_trailer(trailer: &[u8]) -> Option<u64> {
+    if trailer.len() != SFX_TRAILER_SIZE || trailer[12..] != SFX_MAGIC {
+        return None;
+    }
+    Some(u64::from_le_bytes(trailer[0..8].try_into().unwrap()))
+}
+
+/// Returns the start and the end of the payload, after checking its CRC-32 while reading it.
+pub fn read_sfx_payload_bounds<R: Read + Seek>(reader: &mut R) -> Result<(u64, u64), DecodeError> {
+    let trailer_start = reader.seek(SeekFrom::End(0))?
+        .checked_sub(SFX_TRAILER_SIZE as u64)
+        .ok_or(DecodeError::UnexpectedEnd)?;
+    let mut trailer = [0; SFX_TRAILER_SIZE];
+    reader.seek(SeekFrom::Start(trailer_start))?;
+    reader.read_exact(&mut trailer)?;
+
+    let offset = read_sfx_trailer(&trailer).ok_or(DecodeError::InvalidHeader("not a self-extracting archive"))?;
+    if offset > trailer_start {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut payload = reader.take(trailer_start - offset);
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut crc = 0;
+    loop {
+        match payload.read(&mut buffer)? {
+            0 => break,
+            size => crc = update_crc32(crc, &buffer[..size]),
+        }
+    }
+    if crc != u32::from_le_bytes(trailer[8..12].try_into().unwrap()) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok((offset, trailer_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn payload_bounds_are_checked() {
+        let stub = b"a stub executable".as_slice();
+        let mut writer = SfxWriter::new(Vec::new(), stub).unwrap();
+        writer.write_all(b"the payload").unwrap();
+        let archive = writer.finish().unwrap();
+        let end = (archive.len() - SFX_TRAILER_SIZE) as u64;
+        assert_eq!(read_sfx_payload_bounds(&mut Cursor::new(&archive)).unwrap(), (stub.len() as u64, end));
+
+        let mut broken = archive.clone();
+        broken[stub.len() + 1] ^= 1;
+        assert_eq!(read_sfx_payload_bounds(&mut Cursor::new(&broken)), Err(DecodeError::ChecksumMismatch));
+
+        // the payload offset points past the trailer
+        let mut broken = archive.clone();
+        broken[end as usize..end as usize + 8].copy_from_slice(&(end + 1).to_le_bytes());
+        assert_eq!(read_sfx_payload_bounds(&mut Cursor::new(&broken)), Err(DecodeError::UnexpectedEnd));
+
+        assert!(read_sfx_payload_bounds(&mut Cursor::new(&archive[..archive.len() - 1])).is_err());
+        assert_eq!(read_sfx_payload_bounds(&mut Cursor::new(&archive[..10])), Err(DecodeError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,973 @@
+use std::io::{BufRead, Read, Write};
+use std::sync::OnceLock;
+
+use crate::bin_num::bin_to_bytes;
+use crate::crc32::update_crc32;
+use crate::dzip_format::{is_dzip, read_dzip_header, DzipHeader, DICTIONARY_ID_SIZE, DZIP_HEADER_SIZE,
+                         FRAME_HEADER_SIZE};
+use crate::error::DecodeError;
+use crate::huffman::is_prefix_code_lengths;
+use crate::lz77::{Lz77Element, Lz77Encoder};
+use crate::DeflateElementType::{EndBlock, JustElement};
+use crate::{fixed_huffman_lengths, get_deflate_copy_length, smallest_block_encoding, to_deflate_element_type,
+            CompressionLevel, DeflateOffset, DeflateVariant, MAX_BLOCK_ELEMENTS, MAX_HUFFMAN_CODE_LENGTH,
+            SEQUENCE_LENGTH_COMMAND};
+
+/// Compresses everything written to it into a deflate stream, keeping in memory only the
+/// 32 KiB window and the block being collected. Finished blocks go to the inner writer
+/// right away, the last one is written by [`DzipEncoder::finish`].
+///
+/// The output is byte for byte the deflate stream that [`compress`](crate::compress)
+/// puts after the `.dzip` header, however the data is split into writes. The header holds
+/// the size and CRC-32 of the whole data, which are not known while streaming, so it is not
+/// written; [`decompress`](crate::decompress) and `dzip -d` accept the stream without it.
+///
+/// ```
+/// use std::io::Write;
+///
+/// let mut encoder = dzip::DzipEncoder::new(Vec::new());
+/// encoder.write_all(b"hello ").unwrap();
+/// encoder.write_all(b"hello hello").unwrap();
+/// let compressed = encoder.finish().unwrap();
+/// assert_eq!(dzip::decompress(&compressed).unwrap(), b"hello hello hello");
+/// ```
+pub struct DzipEncoder<W: Write> {
+    writer: W,
+    lz77: Lz77Encoder,
+    elements: Vec<Lz77Element>,
+    // the data of the collected elements followed by the data they do not cover yet
+    block_data: Vec<u8>,
+    // the bits of the last incomplete byte
+    bin_result: Vec<bool>,
+    variant: DeflateVariant,
+}
+
+impl<W: Write> DzipEncoder<W> {
+    /// Compresses into `writer` with the default compression level.
+    pub fn new(writer: W) -> DzipEncoder<W> {
+        DzipEncoder::with_variant(writer, DeflateVariant::Deflate)
+    }
+
+    /// Deflate64 streams are written only into zip archives, which name the method.
+    pub(crate) fn with_variant(writer: W, variant: DeflateVariant) -> DzipEncoder<W> {
+        let level = CompressionLevel::Default;
+
+        DzipEncoder {
+            writer,
+            lz77: Lz77Encoder::new(&[], variant.max_shift(), variant.max_coincidence_size(), level.max_chain_length(),
+                                   level.insert_match_positions()),
+            elements: Vec::new(),
+            block_data: Vec::new(),
+            bin_result: Vec::new(),
+            variant,
+        }
+    }
+
+    fn write_block(&mut self, size: usize, is_end_block: bool) -> std::io::Result<()> {
+        let data_size = self.elements[..size].iter().map(|x| x.uncompressed_size()).sum::<usize>();
+        let block = smallest_block_encoding(&self.elements[..size], &self.block_data[..data_size], is_end_block,
+                                            self.bin_result.len(), self.variant);
+        self.elements.drain(..size);
+        self.block_data.drain(..data_size);
+
+        self.bin_result.extend(block);
+        let complete_size = if is_end_block { self.bin_result.len() } else { self.bin_result.len() / 8 * 8 };
+        self.writer.write_all(&bin_to_bytes(&self.bin_result[..complete_size]))?;
+        self.bin_result.drain(..complete_size);
+
+        Ok(())
+    }
+
+    /// A block is written once it is known not to be the last one.
+    fn write_full_blocks(&mut self) -> std::io::Result<()> {
+        while self.elements.len() > MAX_BLOCK_ELEMENTS {
+            self.write_block(MAX_BLOCK_ELEMENTS, false)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last block and returns the inner writer. Without it the stream is incomplete.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.lz77.finish(&mut self.elements);
+        self.write_full_blocks()?;
+        self.write_block(self.elements.len(), true)?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for DzipEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.block_data.extend_from_slice(buf);
+        self.lz77.push(buf, &mut self.elements);
+        self.write_full_blocks()?;
+
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer. The block being collected stays buffered, deflate has no way
+    /// to end it early without changing the output.
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// the compressed data read from the inner reader at once
+const INPUT_BUFFER_SIZE: usize = 1 << 16;
+// the decompressed data decoded at once, before it is handed to the caller
+const OUTPUT_CHUNK_SIZE: usize = 1 << 15;
+
+/// A canonical Huffman code decoded one bit at a time: the number of codes of each length
+/// and the symbols ordered by their codes.
+#[derive(Clone)]
+struct HuffmanDecoder {
+    counts: [i32; MAX_HUFFMAN_CODE_LENGTH + 1],
+    symbols: Vec<i32>,
+}
+
+impl HuffmanDecoder {
+    fn new(lengths: &[i32]) -> Result<HuffmanDecoder, DecodeError> {
+        if !is_prefix_code_lengths(lengths) {
+            return Err(DecodeError::InvalidCodeLengths);
+        }
+
+        let mut counts = [0; MAX_HUFFMAN_CODE_LENGTH + 1];
+        for length in lengths.iter().filter(|x| **x != 0) {
+            counts[*length as usize] += 1;
+        }
+        let mut symbols = (0..lengths.len() as i32).filter(|x| lengths[*x as usize] != 0).collect::<Vec<i32>>();
+        symbols.sort_by_key(|x| lengths[*x as usize]);
+
+        Ok(HuffmanDecoder { counts, symbols })
+    }
+
+    /// Decodes a symbol from the first `bit_count` bits, LSB-first, returning the symbol and
+    /// the length of its code, or None when the code is longer than the bits given.
+    fn decode_bits(&self, bits: u64, bit_count: u32) -> Result<Option<(i32, u32)>, DecodeError> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for length in 1..=MAX_HUFFMAN_CODE_LENGTH {
+            if length as u32 > bit_count {
+                return Ok(None);
+            }
+            code |= ((bits >> (length - 1)) & 1) as i32;
+            let count = self.counts[length];
+            if code - first < count {
+                return Ok(Some((self.symbols[(index + code - first) as usize], length as u32)));
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(DecodeError::InvalidHuffmanCode)
+    }
+}
+
+/// The deflate streams of a file and what to check when each of them ends.
+enum Container {
+    Bare,
+    Dzip(DzipHeader),
+}
+
+struct Frame {
+    number: usize,
+    start: u64,
+    compressed_size: Option<u32>,
+    size: Option<u32>,
+    crc: Option<u32>,
+}
+
+impl Frame {
+    /// The end is known only from the frame header of a checksummed file.
+    fn corrupted_block(&self) -> DecodeError {
+        DecodeError::CorruptedBlock {
+            number: self.number,
+            start: self.start,
+            end: self.start + self.size.unwrap_or_default() as u64,
+        }
+    }
+}
+
+/// Decompresses a `.dzip` file or a bare deflate stream, such as the output of
+/// [`DzipEncoder`], while reading it. Only the 64 KiB input buffer and the last 32 KiB of
+/// the decompressed data (the history back references may point into) are kept, and the
+/// data is handed out as soon as it is decoded.
+///
+/// Data compressed with a preset dictionary fails with [`DecodeError::DictionaryRequired`].
+/// Errors are reported as [`std::io::Error`] of the kind `InvalidData` wrapping a
+/// [`DecodeError`]. The size and CRC-32 of the data are checked at the end, after the data
+/// was already returned.
+///
+/// ```
+/// use std::io::Read;
+///
+/// let compressed = dzip::compress(b"hello hello hello");
+/// let mut answer = String::new();
+/// dzip::DzipDecoder::new(compressed.as_slice()).read_to_string(&mut answer).unwrap();
+/// assert_eq!(answer, "hello hello hello");
+/// ```
+pub struct DzipDecoder<R: Read> {
+    reader: R,
+    input: Vec<u8>,
+    input_position: usize,
+    container: Option<Container>,
+    frame: Option<Frame>,
+    // every frame is an independent deflate stream, back references never cross it
+    inflater: Inflater,
+    is_end: bool,
+    variant: DeflateVariant,
+    output: Vec<u8>,
+    output_size: usize,
+    read_position: usize,
+    size: u64,
+    crc: u32,
+    frame_crc: u32,
+    // the state is not consistent after an error, so the decoder stops at the first one
+    error: Option<DecodeError>,
+}
+
+impl<R: Read> DzipDecoder<R> {
+    /// The format is detected from the first bytes read.
+    pub fn new(reader: R) -> DzipDecoder<R> {
+        DzipDecoder {
+            reader,
+            input: Vec::new(),
+            input_position: 0,
+            container: None,
+            frame: None,
+            inflater: Inflater::new(),
+            is_end: false,
+            variant: DeflateVariant::Deflate,
+            output: vec![0; OUTPUT_CHUNK_SIZE],
+            output_size: 0,
+            read_position: 0,
+            size: 0,
+            crc: 0,
+            frame_crc: 0,
+            error: None,
+        }
+    }
+
+    /// Returns false at the end of the inner reader.
+    fn fill_input(&mut self) -> Result<bool, DecodeError> {
+        self.input.drain(..self.input_position);
+        self.input_position = 0;
+
+        let size = self.input.len();
+        self.input.resize(size + INPUT_BUFFER_SIZE, 0);
+        let read_size = loop {
+            match self.reader.read(&mut self.input[size..]) {
+                Ok(v) => break v,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.input.truncate(size);
+                    return Err(e.into());
+                }
+            }
+        };
+        self.input.truncate(size + read_size);
+
+        Ok(read_size != 0)
+    }
+
+    /// Returns up to `size` next bytes without consuming them, fewer only at the end of the data.
+    fn peek(&mut self, size: usize) -> Result<&[u8], DecodeError> {
+        while self.input.len() - self.input_position < size && self.fill_input()? {}
+        let end = self.input.len().min(self.input_position + size);
+        Ok(&self.input[self.input_position..end])
+    }
+
+    fn read_container(&mut self) -> Result<Container, DecodeError> {
+        let header_data = self.peek(DZIP_HEADER_SIZE + DICTIONARY_ID_SIZE)?;
+        if !is_dzip(header_data) {
+            return Ok(Container::Bare);
+        }
+
+        let header = read_dzip_header(header_data)?;
+        if header.dictionary_id.is_some() {
+            return Err(DecodeError::DictionaryRequired);
+        }
+        self.input_position += header.header_size();
+        self.variant = header.variant();
+
+        Ok(Container::Dzip(header))
+    }
+
+    fn start_frame(&mut self, number: usize) -> Result<(), DecodeError> {
+        let mut frame = Frame {
+            number,
+            start: self.size,
+            compressed_size: None,
+            size: None,
+            crc: None,
+        };
+
+        if let Some(Container::Dzip(header)) = &self.container {
+            if self.size >= header.size && (header.is_seekable() || header.has_checksums()) {
+                self.is_end = true;
+                return Ok(());
+            }
+            if header.has_checksums() {
+                let frame_header = self.peek(FRAME_HEADER_SIZE)?;
+                if frame_header.len() < FRAME_HEADER_SIZE {
+                    return Err(frame.corrupted_block());
+                }
+                frame.compressed_size = Some(u32::from_le_bytes(frame_header[0..4].try_into().unwrap()));
+                frame.size = Some(u32::from_le_bytes(frame_header[4..8].try_into().unwrap()));
+                frame.crc = Some(u32::from_le_bytes(frame_header[8..12].try_into().unwrap()));
+                self.input_position += FRAME_HEADER_SIZE;
+            }
+        }
+
+        self.frame = Some(frame);
+        self.frame_crc = 0;
+        self.inflater = Inflater::with_dictionary(&[], self.variant);
+
+        Ok(())
+    }
+
+    fn end_frame(&mut self) -> Result<(), DecodeError> {
+        let frame = self.frame.take().unwrap();
+
+        let is_corrupted = frame.compressed_size.is_some_and(|x| x as u64 != self.inflater.total_in()) ||
+            frame.size.is_some_and(|x| x as u64 != self.inflater.total_out()) ||
+            frame.crc.is_some_and(|x| x != self.frame_crc);
+        if is_corrupted {
+            return Err(frame.corrupted_block());
+        }
+
+        if self.is_framed() {
+            self.start_frame(frame.number + 1)
+        } else {
+            self.is_end = true;
+            Ok(())
+        }
+    }
+
+    fn is_framed(&self) -> bool {
+        match &self.container {
+            Some(Container::Dzip(header)) => header.is_seekable() || header.has_checksums(),
+            _ => false,
+        }
+    }
+
+    /// An error inside a frame of a framed file is reported as the corruption of the frame,
+    /// the same as the one-shot decoder does.
+    fn frame_error(&self, e: DecodeError) -> DecodeError {
+        match &self.frame {
+            Some(frame) if self.is_framed() => frame.corrupted_block(),
+            _ => e,
+        }
+    }
+
+    fn check_end(&self) -> Result<(), DecodeError> {
+        if let Some(Container::Dzip(header)) = &self.container {
+            if self.size != header.size {
+                return Err(DecodeError::SizeMismatch);
+            }
+            if self.crc != header.crc {
+                return Err(DecodeError::ChecksumMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&mut self) -> Result<(), DecodeError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        let result = self.decode_more();
+        if let Err(e) = &result {
+            self.error = Some(e.clone());
+        }
+        result
+    }
+
+    /// Decodes until there is some data to return or the end of the data.
+    fn decode_more(&mut self) -> Result<(), DecodeError> {
+        if self.container.is_none() {
+            self.container = Some(self.read_container()?);
+            self.start_frame(0)?;
+        }
+
+        while self.read_position == self.output_size && !self.is_end {
+            let (total_in, total_out) = (self.inflater.total_in(), self.inflater.total_out());
+            let status = self.inflater.feed(&self.input[self.input_position..], &mut self.output)
+                .map_err(|e| self.frame_error(e))?;
+            self.input_position += (self.inflater.total_in() - total_in) as usize;
+            self.output_size = (self.inflater.total_out() - total_out) as usize;
+            self.read_position = 0;
+
+            let decoded = &self.output[..self.output_size];
+            self.size += decoded.len() as u64;
+            self.crc = update_crc32(self.crc, decoded);
+            self.frame_crc = update_crc32(self.frame_crc, decoded);
+
+            match status {
+                Status::NeedsInput => {
+                    if !self.fill_input()? {
+                        return Err(self.frame_error(DecodeError::UnexpectedEnd));
+                    }
+                }
+                Status::OutputFull => {}
+                Status::Done => self.end_frame()?,
+            }
+        }
+
+        if self.is_end && self.read_position == self.output_size {
+            self.check_end()?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for DzipDecoder<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.decode().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok(&self.output[self.read_position..self.output_size])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.read_position = (self.read_position + amount).min(self.output_size);
+    }
+}
+
+impl<R: Read> Read for DzipDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.fill_buf()?;
+        let size = buf.len().min(data.len());
+        buf[..size].copy_from_slice(&data[..size]);
+        self.consume(size);
+
+        Ok(size)
+    }
+}
+
+/// Where [`Inflater::feed`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// All the input was consumed and the stream continues.
+    NeedsInput,
+    /// The output buffer is full, the rest of the input was not consumed.
+    OutputFull,
+    /// The last block ended, the input after it was not consumed.
+    Done,
+}
+
+/// The bits taken from the input but not decoded yet, LSB-first.
+struct BitBuffer {
+    bits: u64,
+    bit_count: u32,
+}
+
+impl BitBuffer {
+    /// Takes input bytes until `count` bits are buffered, one byte at a time so that no byte
+    /// is consumed before it is needed. Returns false when the input runs out first.
+    fn fill(&mut self, input: &[u8], consumed: &mut usize, count: u32) -> bool {
+        while self.bit_count < count {
+            let Some(&byte) = input.get(*consumed) else {
+                return false;
+            };
+            self.bits |= (byte as u64) << self.bit_count;
+            self.bit_count += 8;
+            *consumed += 1;
+        }
+        true
+    }
+
+    /// Must be called only after `fill` returned true for at least `count` bits.
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        self.bit_count -= count;
+        value
+    }
+
+    fn align_to_byte(&mut self) {
+        self.read(self.bit_count % 8);
+    }
+
+    /// Decodes the next symbol without consuming its code, None when the input runs out
+    /// in the middle of it.
+    fn peek_symbol(&mut self, decoder: &HuffmanDecoder, input: &[u8], consumed: &mut usize)
+                   -> Result<Option<(i32, u32)>, DecodeError> {
+        loop {
+            if let Some(v) = decoder.decode_bits(self.bits, self.bit_count)? {
+                return Ok(Some(v));
+            }
+            if !self.fill(input, consumed, self.bit_count + 1) {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// The next part of the stream to decode. Every step reads all its bits at once or none,
+/// so the decoder can stop between any two bits of the input.
+enum InflateState {
+    BlockHeader,
+    StoredHeader,
+    Stored(usize),
+    DynamicHeader,
+    CodeLengthLengths { hlit: usize, hdist: usize, hclen: usize, lengths: Vec<i32> },
+    CodeLengths { hlit: usize, count: usize, decoder: HuffmanDecoder, lengths: Vec<i32> },
+    Symbol,
+    Distance(usize),
+    Copy { length: usize, distance: usize },
+    Done,
+}
+
+/// Decompresses a bare deflate stream that arrives in fragments of any size, such as
+/// network packets. [`Inflater::feed`] decodes as much as the input and the output buffer
+/// allow and keeps the bits of an unfinished header or Huffman code until the next call,
+/// so the stream may be split at any bit. Only the last 32 KiB of the output are kept.
+///
+/// The number of bytes consumed and written by a call are the differences of
+/// [`Inflater::total_in`] and [`Inflater::total_out`]. After [`Status::Done`] the total input
+/// is the size of the compressed stream, whatever follows it was not consumed.
+///
+/// ```
+/// use std::io::Write;
+/// use dzip::{Inflater, Status};
+///
+/// let mut encoder = dzip::DzipEncoder::new(Vec::new());
+/// encoder.write_all(b"hello hello hello").unwrap();
+/// let compressed = encoder.finish().unwrap();
+///
+/// let mut inflater = Inflater::new();
+/// let mut output = [0; 4];
+/// let mut answer = Vec::new();
+/// let mut status = Status::NeedsInput;
+/// for mut packet in compressed.chunks(3) {
+///     loop {
+///         let (total_in, total_out) = (inflater.total_in(), inflater.total_out());
+///         status = inflater.feed(packet, &mut output).unwrap();
+///         packet = &packet[(inflater.total_in() - total_in) as usize..];
+///         answer.extend_from_slice(&output[..(inflater.total_out() - total_out) as usize]);
+///         if status != Status::OutputFull {
+///             break;
+///         }
+///     }
+/// }
+/// assert_eq!(status, Status::Done);
+/// assert_eq!(answer, b"hello hello hello");
+/// ```
+pub struct Inflater {
+    state: InflateState,
+    is_last_block: bool,
+    // the literal and length code followed by the distance code of the current block
+    codes: Option<(HuffmanDecoder, HuffmanDecoder)>,
+    buffer: BitBuffer,
+    history: Vec<u8>,
+    variant: DeflateVariant,
+    total_in: u64,
+    total_out: u64,
+    // the state is not consistent after an error, so the decoder stops at the first one
+    error: Option<DecodeError>,
+}
+
+impl Default for Inflater {
+    fn default() -> Self {
+        Inflater::new()
+    }
+}
+
+impl Inflater {
+    /// Starts at the beginning of a plain deflate stream.
+    pub fn new() -> Inflater {
+        Inflater::with_dictionary(&[], DeflateVariant::Deflate)
+    }
+
+    /// The preset dictionary only seeds the history that back references may point into,
+    /// it is not a part of the output.
+    pub(crate) fn with_dictionary(dictionary: &[u8], variant: DeflateVariant) -> Inflater {
+        Inflater {
+            state: InflateState::BlockHeader,
+            is_last_block: false,
+            codes: None,
+            buffer: BitBuffer { bits: 0, bit_count: 0 },
+            history: dictionary.to_vec(),
+            variant,
+            total_in: 0,
+            total_out: 0,
+            error: None,
+        }
+    }
+
+    /// The number of compressed bytes consumed so far.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// The number of decompressed bytes written so far.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Decodes `input` into `output` until one of them runs out or the stream ends.
+    /// Once an error is returned, every later call returns it again.
+    pub fn feed(&mut self, input: &[u8], output: &mut [u8]) -> Result<Status, DecodeError> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+
+        let mut consumed = 0;
+        let mut written = 0;
+        let result = loop {
+            match self.step(input, &mut consumed, output, &mut written) {
+                Ok(None) => self.discard_history(),
+                Ok(Some(status)) => break Ok(status),
+                Err(e) => break Err(e),
+            }
+        };
+        self.total_in += consumed as u64;
+        self.total_out += written as u64;
+
+        if let Err(e) = &result {
+            self.error = Some(e.clone());
+        }
+        result
+    }
+
+    fn push_output(&mut self, value: u8, output: &mut [u8], written: &mut usize) {
+        output[*written] = value;
+        *written += 1;
+        self.history.push(value);
+    }
+
+    fn end_block(&mut self) {
+        self.state = if self.is_last_block { InflateState::Done } else { InflateState::BlockHeader };
+    }
+
+    /// Keeps only the history that back references may still point into.
+    fn discard_history(&mut self) {
+        let history_size = self.variant.max_shift() as usize;
+        if self.history.len() >= 2 * history_size {
+            self.history.drain(..self.history.len() - history_size);
+        }
+    }
+
+    /// Decodes one part of the stream. Returns a status when it can not continue.
+    fn step(&mut self, input: &[u8], consumed: &mut usize, output: &mut [u8], written: &mut usize)
+            -> Result<Option<Status>, DecodeError> {
+        let buffer = &mut self.buffer;
+
+        match &mut self.state {
+            InflateState::BlockHeader => {
+                if !buffer.fill(input, consumed, 3) {
+                    return Ok(Some(Status::NeedsInput));
+                }
+                self.is_last_block = buffer.read(1) == 1;
+                self.state = match buffer.read(2) {
+                    0 => InflateState::StoredHeader,
+                    1 => {
+                        // the codes are the same for every fixed block, building them costs more than decoding a short block
+                        static FIXED_CODES: OnceLock<(HuffmanDecoder, HuffmanDecoder)> = OnceLock::new();
+                        self.codes = Some(FIXED_CODES.get_or_init(|| {
+                            let (deflate_lengths, offset_lengths) = fixed_huffman_lengths();
+                            (HuffmanDecoder::new(&deflate_lengths).unwrap(), HuffmanDecoder::new(&offset_lengths).unwrap())
+                        }).clone());
+                        InflateState::Symbol
+                    }
+                    2 => InflateState::DynamicHeader,
+                    block_type => return Err(DecodeError::UnsupportedBlockType(block_type as u8)),
+                };
+            }
+            InflateState::StoredHeader => {
+                buffer.align_to_byte();
+                if !buffer.fill(input, consumed, 32) {
+                    return Ok(Some(Status::NeedsInput));
+                }
+                let length = buffer.read(16);
+                let inverted_length = buffer.read(16);
+                if length != !inverted_length & 0xffff {
+                    return Err(DecodeError::InvalidBlockLength);
+                }
+                self.state = InflateState::Stored(length as usize);
+            }
+            InflateState::Stored(remaining) => {
+                if *remaining == 0 {
+                    self.end_block();
+                    return Ok(None);
+                }
+                if *written == output.len() {
+                    return Ok(Some(Status::OutputFull));
+                }
+                if *consumed == input.len() {
+                    return Ok(Some(Status::NeedsInput));
+                }
+
+                // the bit buffer is empty after the aligned header, the data is copied as is
+                let size = (*remaining).min(input.len() - *consumed).min(output.len() - *written);
+                let data = &input[*consumed..*consumed + size];
+                output[*written..*written + size].copy_from_slice(data);
+                self.history.extend_from_slice(data);
+                *consumed += size;
+                *written += size;
+                *remaining -= size;
+            }
+            InflateState::DynamicHeader => {
+                if !buffer.fill(input, consumed, 14) {
+                    return Ok(Some(Status::NeedsInput));
+                }
+                self.state = InflateState::CodeLengthLengths {
+                    hlit: buffer.read(5) as usize + 257,
+                    hdist: buffer.read(5) as usize + 1,
+                    hclen: buffer.read(4) as usize + 4,
+                    lengths: Vec::new(),
+                };
+            }
+            InflateState::CodeLengthLengths { hlit, hdist, hclen, lengths } => {
+                if lengths.len() < *hclen {
+                    if !buffer.fill(input, consumed, 3) {
+                        return Ok(Some(Status::NeedsInput));
+                    }
+                    lengths.push(buffer.read(3) as i32);
+                    return Ok(None);
+                }
+
+                let mut deflate_len_lengths = vec![0; SEQUENCE_LENGTH_COMMAND.len()];
+                for (length_index, length) in SEQUENCE_LENGTH_COMMAND.iter().zip(lengths.iter()) {
+                    deflate_len_lengths[*length_index as usize] = *length;
+                }
+                let count = *hlit + *hdist;
+                self.state = InflateState::CodeLengths {
+                    hlit: *hlit,
+                    count,
+                    decoder: HuffmanDecoder::new(&deflate_len_lengths)?,
+                    lengths: Vec::with_capacity(count),
+                };
+            }
+            InflateState::CodeLengths { hlit, count, decoder, lengths } => {
+                if lengths.len() == *count {
+                    self.codes = Some((HuffmanDecoder::new(&lengths[..*hlit])?, HuffmanDecoder::new(&lengths[*hlit..])?));
+                    self.state = InflateState::Symbol;
+                    return Ok(None);
+                }
+
+                let Some((symbol, code_length)) = buffer.peek_symbol(decoder, input, consumed)? else {
+                    return Ok(Some(Status::NeedsInput));
+                };
+                let number_additional_bits = match symbol {
+                    16 => 2,
+                    17 => 3,
+                    18 => 7,
+                    _ => 0,
+                };
+                if !buffer.fill(input, consumed, code_length + number_additional_bits) {
+                    return Ok(Some(Status::NeedsInput));
+                }
+                buffer.read(code_length);
+
+                let (length, repetitions) = match symbol {
+                    v @ 0..=15 => (v, 1),
+                    16 => (*lengths.last().ok_or(DecodeError::InvalidCodeLengths)?, buffer.read(2) + 3),
+                    17 => (0, buffer.read(3) + 3),
+                    18 => (0, buffer.read(7) + 11),
+                    v => return Err(DecodeError::InvalidSymbol(v))
+                };
+                if lengths.len() + repetitions as usize > *count {
+                    return Err(DecodeError::InvalidCodeLengths);
+                }
+                lengths.extend(std::iter::repeat_n(length, repetitions as usize));
+            }
+            InflateState::Symbol => {
+                let (lang_deflate_elements, _) = self.codes.as_ref().unwrap();
+                let Some((symbol, code_length)) = buffer.peek_symbol(lang_deflate_elements, input, consumed)? else {
+                    return Ok(Some(Status::NeedsInput));
+                };
+
+                match to_deflate_element_type(symbol, self.variant)? {
+                    JustElement(v) => {
+                        if *written == output.len() {
+                            return Ok(Some(Status::OutputFull));
+                        }
+                        buffer.read(code_length);
+                        self.push_output(v, output, written);
+                    }
+                    EndBlock => {
+                        buffer.read(code_length);
+                        self.end_block();
+                    }
+                    elem => {
+                        let number_additional_bits = elem.get_number_additional_bits() as u32;
+                        if !buffer.fill(input, consumed, code_length + number_additional_bits) {
+                            return Ok(Some(Status::NeedsInput));
+                        }
+                        buffer.read(code_length);
+                        let length_match = get_deflate_copy_length(&elem) as usize +
+                            buffer.read(number_additional_bits) as usize;
+                        self.state = InflateState::Distance(length_match);
+                    }
+                }
+            }
+            InflateState::Distance(length_match) => {
+                let (_, lang_deflate_offset_elements) = self.codes.as_ref().unwrap();
+                let Some((offset_symbol, code_length)) =
+                    buffer.peek_symbol(lang_deflate_offset_elements, input, consumed)? else {
+                    return Ok(Some(Status::NeedsInput));
+                };
+                if offset_symbol >= self.variant.number_offset_codes() {
+                    return Err(DecodeError::InvalidSymbol(offset_symbol));
+                }
+                let offset_elem = DeflateOffset {
+                    main_value: offset_symbol as u8,
+                    additional_bits: 0,
+                };
+
+                let number_additional_bits = offset_elem.get_number_additional_bits() as u32;
+                if !buffer.fill(input, consumed, code_length + number_additional_bits) {
+                    return Ok(Some(Status::NeedsInput));
+                }
+                buffer.read(code_length);
+                let offset = offset_elem.get_base_offset() as usize + buffer.read(number_additional_bits) as usize;
+                if offset > self.history.len() {
+                    return Err(DecodeError::InvalidDistance(offset));
+                }
+                self.state = InflateState::Copy { length: *length_match, distance: offset };
+            }
+            InflateState::Copy { length, distance } => {
+                let (length, distance) = (*length, *distance);
+                let size = length.min(output.len() - *written);
+                for _ in 0..size {
+                    self.push_output(self.history[self.history.len() - distance], output, written);
+                }
+
+                if size < length {
+                    self.state = InflateState::Copy { length: length - size, distance };
+                    return Ok(Some(Status::OutputFull));
+                }
+                self.state = InflateState::Symbol;
+            }
+            InflateState::Done => {
+                // the padding of the last byte belongs to the stream
+                buffer.align_to_byte();
+                return Ok(Some(Status::Done));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dzip_format::{dzip_decoding, dzip_framed_encoding};
+    use crate::test_data::random_bytes;
+
+    /// Writes the data in chunks of `chunk_size` bytes, with an empty write before each one.
+    fn streaming_encoding(data: &[u8], chunk_size: usize, empty_writes: bool) -> Vec<u8> {
+        let mut encoder = DzipEncoder::new(Vec::new());
+        for chunk in data.chunks(chunk_size) {
+            if empty_writes {
+                assert_eq!(encoder.write(&[]).unwrap(), 0);
+            }
+            encoder.write_all(chunk).unwrap();
+        }
+        if empty_writes {
+            encoder.write_all(&[]).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    fn streaming_decoding(data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let mut answer = Vec::new();
+        DzipDecoder::new(data).read_to_end(&mut answer)
+            .map_err(|e| *e.into_inner().unwrap().downcast::<DecodeError>().unwrap())?;
+        Ok(answer)
+    }
+
+    #[test]
+    fn streaming_encoding_is_one_shot_encoding() {
+        // the random bytes are more literals than one block holds
+        let mut long = random_bytes(70000, 1);
+        long.extend(b"a block boundary in the middle of repeated text ".repeat(2000));
+        for data in [Vec::new(), b"a".to_vec(), b"hello hello hello".to_vec(), long] {
+            let expected = &crate::compress(&data)[DZIP_HEADER_SIZE..];
+            for chunk_size in [1, 7919, data.len().max(1)] {
+                assert_eq!(streaming_encoding(&data, chunk_size, false), expected,
+                           "{} bytes written by {}", data.len(), chunk_size);
+            }
+            assert_eq!(streaming_encoding(&data, 4099, true), expected, "{} bytes with empty writes", data.len());
+        }
+    }
+
+    #[test]
+    fn inflater_decodes_byte_by_byte() {
+        let (level, variant) = (CompressionLevel::Default, DeflateVariant::Deflate);
+        let lz77 = |data: &[u8]| crate::lz77::encoding_lz77(data, &[], variant.max_shift(), variant.max_coincidence_size(),
+                                                            level.max_chain_length(), level.insert_match_positions());
+        let fixed_data = b"fixed fixed fixed";
+        let dynamic_data = b"a dynamic block, a dynamic block, the dynamic block".repeat(20);
+
+        let mut bits = crate::stored_block_encoding(b"stored ", false, 0);
+        let fixed_block = crate::fixed_block_encoding(&lz77(fixed_data), false, variant);
+        assert_eq!(fixed_block[1..3], [true, false]);
+        bits.extend(fixed_block);
+        let dynamic_block = crate::deflate_block_encoding(&lz77(&dynamic_data), true, variant);
+        assert_eq!(dynamic_block[1..3], [false, true]);
+        bits.extend(dynamic_block);
+        let mut compressed = bin_to_bytes(&bits);
+        let stream_size = compressed.len() as u64;
+        compressed.extend(b"trailing bytes");
+
+        let mut inflater = Inflater::new();
+        let mut answer = Vec::new();
+        let mut statuses = Vec::new();
+        loop {
+            let (start, written) = (inflater.total_in() as usize, inflater.total_out());
+            let mut output = [0];
+            let status = inflater.feed(&compressed[start..start + 1], &mut output).unwrap();
+            answer.extend_from_slice(&output[..(inflater.total_out() - written) as usize]);
+            if statuses.last() != Some(&status) {
+                statuses.push(status);
+            }
+            if status == Status::Done {
+                break;
+            }
+        }
+
+        assert!(statuses.contains(&Status::NeedsInput));
+        assert!(statuses.contains(&Status::OutputFull));
+        assert_eq!(statuses.last(), Some(&Status::Done));
+        assert_eq!(inflater.total_in(), stream_size);
+        assert_eq!(answer, [b"stored ".as_slice(), fixed_data, &dynamic_data].concat());
+        // the input after the stream is never consumed
+        assert_eq!(inflater.feed(&compressed[stream_size as usize..], &mut [0; 8]), Ok(Status::Done));
+        assert_eq!(inflater.total_in(), stream_size);
+    }
+
+    #[test]
+    fn broken_frames_are_reported_as_one_shot() {
+        let data = b"frames of a checksummed file ".repeat(100);
+        let compressed = dzip_framed_encoding(data, None, DeflateVariant::Deflate, 1000, false, true);
+
+        for size in DZIP_HEADER_SIZE..compressed.len() {
+            let truncated = &compressed[..size];
+            assert_eq!(streaming_decoding(truncated).err(),
+                       dzip_decoding(truncated.to_vec(), None, DeflateVariant::Deflate).err(), "truncated to {}", size);
+        }
+        // a changed padding bit at the end of a frame is not an error for either of them
+        for index in DZIP_HEADER_SIZE..compressed.len() {
+            let mut broken = compressed.clone();
+            broken[index] ^= 0x10;
+            assert_eq!(streaming_decoding(&broken).err(), dzip_decoding(broken, None, DeflateVariant::Deflate).err(),
+                       "byte {} changed", index);
+        }
+    }
+
+    #[test]
+    fn broken_seekable_frame_is_reported() {
+        let data = b"frames of a seekable file ".repeat(100);
+        let mut compressed = dzip_framed_encoding(data, None, DeflateVariant::Deflate, 1000, true, false);
+        let frame_start = DZIP_HEADER_SIZE + u32::from_le_bytes(compressed[compressed.len() - 32..compressed.len() - 28]
+            .try_into().unwrap()) as usize;
+        compressed[frame_start] = 0xff;
+
+        assert!(matches!(streaming_decoding(&compressed), Err(DecodeError::CorruptedBlock { number: 1, start: 1000, .. })));
+    }
+}
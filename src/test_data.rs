@@ -0,0 +1,12 @@
+//! Data shared by the tests. The integration tests include this file with `#[path]`.
+
+/// Incompressible bytes, the same for the same seed. The seed must not be zero.
+pub fn random_bytes(size: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..size).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state >> 24) as u8
+    }).collect()
+}
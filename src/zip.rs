@@ -0,0 +1,879 @@
+//! Zip archives (PKWARE APPNOTE) with stored, deflate and Deflate64 entries and Zip64
+//! fields for large archives.
+
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::crc32::update_crc32;
+use crate::error::DecodeError;
+use crate::{DeflateVariant, DzipEncoder, Inflater, Status, MAX_STORED_BLOCK_SIZE};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+
+const LOCAL_FILE_HEADER_SIZE: usize = 30;
+const CENTRAL_DIRECTORY_RECORD_SIZE: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE: usize = 56;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE: usize = 20;
+const MAX_COMMENT_SIZE: usize = 65535;
+
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+const ZIP64_EXTRA_FIELD_SIZE: u16 = 16;
+// sizes and offsets from this value on, and entry counts from `ZIP64_ENTRIES_LIMIT` on,
+// are stored in Zip64 fields and replaced with a marker in the classic ones
+const ZIP64_LIMIT: u64 = 0xffffffff;
+const ZIP64_ENTRIES_LIMIT: usize = 0xffff;
+const ZIP64_MARKER: u32 = 0xffffffff;
+const ZIP64_ENTRIES_MARKER: u16 = 0xffff;
+
+const VERSION_NEEDED: u16 = 20;
+const VERSION_NEEDED_DEFLATE64: u16 = 21;
+const VERSION_NEEDED_ZIP64: u16 = 45;
+const VERSION_MADE_BY_UNIX: u16 = (3 << 8) | VERSION_NEEDED_ZIP64;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+const FLAG_UTF8_NAME: u16 = 0x0800;
+
+const DOS_DIRECTORY_ATTRIBUTE: u32 = 0x10;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATE: u16 = 8;
+const METHOD_DEFLATE64: u16 = 9;
+
+// the data read and decoded at once
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// An entry to write, its data is read by [`ZipWriter::add_entry`].
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    /// The path inside the archive, separated by `/`, ending with `/` for a directory.
+    pub name: String,
+    /// The expected size of the data, it decides whether the local header needs Zip64 fields.
+    pub size: u64,
+    /// The modification time in seconds since the Unix epoch.
+    pub mtime: u32,
+    /// The Unix permission bits.
+    pub mode: u32,
+    /// True for a directory, which has no data.
+    pub is_directory: bool,
+}
+
+/// An entry as the central directory describes it.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CentralDirectoryRecord {
+    /// The path inside the archive, separated by `/`.
+    pub name: String,
+    pub(crate) flags: u16,
+    /// The compression method: 0 stored, 8 deflate, 9 Deflate64.
+    pub method: u16,
+    pub(crate) dos_time: u16,
+    pub(crate) dos_date: u16,
+    /// The CRC-32 of the data.
+    pub crc: u32,
+    /// The size of the data in the archive.
+    pub compressed_size: u64,
+    /// The size of the data.
+    pub size: u64,
+    pub(crate) external_attributes: u32,
+    pub(crate) offset: u64,
+}
+
+impl CentralDirectoryRecord {
+    /// Returns true if the name ends with `/`.
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// The Unix file type and permission bits, 0 if the archive was not written on Unix.
+    pub fn unix_mode(&self) -> u32 {
+        self.external_attributes >> 16
+    }
+
+    /// The modification time as year, month, day, hour and minute, as stored: dzip writes UTC,
+    /// most other tools local time.
+    pub fn date_time(&self) -> (u32, u32, u32, u32, u32) {
+        let date = self.dos_date as u32;
+        let time = self.dos_time as u32;
+
+        ((date >> 9) + 1980, (date >> 5) & 0x0f, date & 0x1f, time >> 11, (time >> 5) & 0x3f)
+    }
+}
+
+fn days_to_civil(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn to_dos_date_time(mtime: u32) -> (u16, u16) {
+    let (year, month, day) = days_to_civil(mtime as i64 / 86400);
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+
+    let seconds = mtime % 86400;
+    let time = ((seconds / 3600) << 11) | (((seconds / 60) % 60) << 5) | ((seconds % 60) / 2);
+    let date = (((year - 1980).min(127) as u32) << 9) | (month << 5) | day;
+
+    (time as u16, date as u16)
+}
+
+fn push_u16(answer: &mut Vec<u8>, value: u16) {
+    answer.extend(value.to_le_bytes());
+}
+
+fn push_u32(answer: &mut Vec<u8>, value: u32) {
+    answer.extend(value.to_le_bytes());
+}
+
+fn push_u64(answer: &mut Vec<u8>, value: u64) {
+    answer.extend(value.to_le_bytes());
+}
+
+/// Pushes the value, or the Zip64 marker when the value reaches `zip64_limit`.
+fn push_u32_or_zip64(answer: &mut Vec<u8>, value: u64, zip64_limit: u64) {
+    push_u32(answer, if value >= zip64_limit { ZIP64_MARKER } else { value as u32 });
+}
+
+fn read_u16(data: &[u8], index: usize) -> Result<u16, DecodeError> {
+    let bytes = data.get(index..index + 2).ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], index: usize) -> Result<u32, DecodeError> {
+    let bytes = data.get(index..index + 4).ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], index: usize) -> Result<u64, DecodeError> {
+    let bytes = data.get(index..index + 8).ok_or(DecodeError::UnexpectedEnd)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn version_needed(method: u16, is_zip64: bool) -> u16 {
+    if is_zip64 {
+        VERSION_NEEDED_ZIP64
+    } else if method == METHOD_DEFLATE64 {
+        VERSION_NEEDED_DEFLATE64
+    } else {
+        VERSION_NEEDED
+    }
+}
+
+fn general_purpose_flags(entry: &ZipEntry) -> u16 {
+    if entry.is_directory {
+        FLAG_UTF8_NAME
+    } else {
+        FLAG_DATA_DESCRIPTOR | FLAG_UTF8_NAME
+    }
+}
+
+/// The encoder falls back to stored blocks, so the data grows by a few headers at most.
+fn max_compressed_size(size: u64) -> u64 {
+    size + (size / MAX_STORED_BLOCK_SIZE as u64 + 1) * 16
+}
+
+/// The sizes are given by the data descriptor, a Zip64 local header only reserves both of them.
+fn write_local_file_header(answer: &mut Vec<u8>, entry: &ZipEntry, method: u16, dos_time: u16, dos_date: u16,
+                           is_zip64: bool) {
+    push_u32(answer, LOCAL_FILE_HEADER_SIGNATURE);
+    push_u16(answer, version_needed(method, is_zip64));
+    push_u16(answer, general_purpose_flags(entry));
+    push_u16(answer, method);
+    push_u16(answer, dos_time);
+    push_u16(answer, dos_date);
+    push_u32(answer, 0);
+    push_u32(answer, if is_zip64 { ZIP64_MARKER } else { 0 });
+    push_u32(answer, if is_zip64 { ZIP64_MARKER } else { 0 });
+    push_u16(answer, entry.name.len() as u16);
+    push_u16(answer, if is_zip64 { 4 + ZIP64_EXTRA_FIELD_SIZE } else { 0 });
+    answer.extend(entry.name.as_bytes());
+    if is_zip64 {
+        push_u16(answer, ZIP64_EXTRA_FIELD_ID);
+        push_u16(answer, ZIP64_EXTRA_FIELD_SIZE);
+        push_u64(answer, 0);
+        push_u64(answer, 0);
+    }
+}
+
+/// The Zip64 extra field holds only the values that do not fit into the record, in this order.
+fn zip64_extra_field(record: &CentralDirectoryRecord, zip64_limit: u64) -> Vec<u8> {
+    let mut values = Vec::new();
+    for value in [record.size, record.compressed_size, record.offset] {
+        if value >= zip64_limit {
+            push_u64(&mut values, value);
+        }
+    }
+    if values.is_empty() {
+        return values;
+    }
+
+    let mut extra = Vec::new();
+    push_u16(&mut extra, ZIP64_EXTRA_FIELD_ID);
+    push_u16(&mut extra, values.len() as u16);
+    extra.extend(values);
+
+    extra
+}
+
+fn write_central_directory_record(answer: &mut Vec<u8>, record: &CentralDirectoryRecord, zip64_limit: u64) {
+    let extra = zip64_extra_field(record, zip64_limit);
+
+    push_u32(answer, CENTRAL_DIRECTORY_SIGNATURE);
+    push_u16(answer, VERSION_MADE_BY_UNIX);
+    push_u16(answer, version_needed(record.method, !extra.is_empty()));
+    push_u16(answer, record.flags);
+    push_u16(answer, record.method);
+    push_u16(answer, record.dos_time);
+    push_u16(answer, record.dos_date);
+    push_u32(answer, record.crc);
+    push_u32_or_zip64(answer, record.compressed_size, zip64_limit);
+    push_u32_or_zip64(answer, record.size, zip64_limit);
+    push_u16(answer, record.name.len() as u16);
+    push_u16(answer, extra.len() as u16);
+    push_u16(answer, 0);
+    push_u16(answer, 0);
+    push_u16(answer, 0);
+    push_u32(answer, record.external_attributes);
+    push_u32_or_zip64(answer, record.offset, zip64_limit);
+    answer.extend(record.name.as_bytes());
+    answer.extend(extra);
+}
+
+/// `offset` is the position of the record in the archive.
+fn write_zip64_end_of_central_directory(answer: &mut Vec<u8>, offset: u64, number_entries: usize,
+                                        central_directory_size: u64, central_directory_offset: u64) {
+    push_u32(answer, ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    push_u64(answer, (ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE - 12) as u64);
+    push_u16(answer, VERSION_MADE_BY_UNIX);
+    push_u16(answer, VERSION_NEEDED_ZIP64);
+    push_u32(answer, 0);
+    push_u32(answer, 0);
+    push_u64(answer, number_entries as u64);
+    push_u64(answer, number_entries as u64);
+    push_u64(answer, central_directory_size);
+    push_u64(answer, central_directory_offset);
+
+    push_u32(answer, ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+    push_u32(answer, 0);
+    push_u64(answer, offset);
+    push_u32(answer, 1);
+}
+
+/// Counts the bytes written, the offsets in an archive start from its first byte.
+struct CountingWriter<W: Write> {
+    writer: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.writer.write(buf)?;
+        self.count += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes a zip archive entry by entry. The data of an entry is compressed while it is read,
+/// only the central directory records are kept until [`ZipWriter::finish`].
+pub struct ZipWriter<W: Write> {
+    writer: CountingWriter<W>,
+    records: Vec<CentralDirectoryRecord>,
+    variant: DeflateVariant,
+    zip64_limit: u64,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// File entries are compressed with `variant`, Deflate64 entries need a reader that
+    /// supports the method 9.
+    pub fn new(writer: W, variant: DeflateVariant) -> ZipWriter<W> {
+        ZipWriter::with_zip64_limit(writer, variant, ZIP64_LIMIT)
+    }
+
+    /// Sizes and offsets from `zip64_limit` on are written to Zip64 fields. Only the tests
+    /// lower it, to reach these fields with small files.
+    pub(crate) fn with_zip64_limit(writer: W, variant: DeflateVariant, zip64_limit: u64) -> ZipWriter<W> {
+        ZipWriter {
+            writer: CountingWriter { writer, count: 0 },
+            records: Vec::new(),
+            variant,
+            zip64_limit,
+        }
+    }
+
+    /// Reads the data of a file entry to its end, the data of a directory is not read.
+    pub fn add_entry<R: Read>(&mut self, entry: &ZipEntry, data: R) -> std::io::Result<()> {
+        let offset = self.writer.count;
+        let (dos_time, dos_date) = to_dos_date_time(entry.mtime);
+        let method = match self.variant {
+            _ if entry.is_directory => METHOD_STORED,
+            DeflateVariant::Deflate => METHOD_DEFLATE,
+            DeflateVariant::Deflate64 => METHOD_DEFLATE64,
+        };
+        let is_zip64 = !entry.is_directory && max_compressed_size(entry.size) >= self.zip64_limit;
+
+        let mut header = Vec::new();
+        write_local_file_header(&mut header, entry, method, dos_time, dos_date, is_zip64);
+        self.writer.write_all(&header)?;
+
+        let (crc, size, compressed_size) = if entry.is_directory {
+            (0, 0, 0)
+        } else {
+            self.write_compressed(data)?
+        };
+        if !is_zip64 && (size >= self.zip64_limit || compressed_size >= self.zip64_limit) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                           format!("{} grew while it was compressed", entry.name)));
+        }
+
+        if !entry.is_directory {
+            let mut descriptor = Vec::new();
+            push_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+            push_u32(&mut descriptor, crc);
+            if is_zip64 {
+                push_u64(&mut descriptor, compressed_size);
+                push_u64(&mut descriptor, size);
+            } else {
+                push_u32(&mut descriptor, compressed_size as u32);
+                push_u32(&mut descriptor, size as u32);
+            }
+            self.writer.write_all(&descriptor)?;
+        }
+
+        self.records.push(CentralDirectoryRecord {
+            name: entry.name.clone(),
+            flags: general_purpose_flags(entry),
+            method,
+            dos_time,
+            dos_date,
+            crc,
+            compressed_size,
+            size,
+            external_attributes: (entry.mode << 16) | if entry.is_directory { DOS_DIRECTORY_ATTRIBUTE } else { 0 },
+            offset,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the CRC-32 and the size of the data, and the size of the compressed data.
+    fn write_compressed<R: Read>(&mut self, mut data: R) -> std::io::Result<(u32, u64, u64)> {
+        let start = self.writer.count;
+        let mut encoder = DzipEncoder::with_variant(&mut self.writer, self.variant);
+
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut crc = 0;
+        let mut size = 0;
+        loop {
+            let read_size = match data.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(v) => v,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            crc = update_crc32(crc, &buffer[..read_size]);
+            size += read_size as u64;
+            encoder.write_all(&buffer[..read_size])?;
+        }
+        encoder.finish()?;
+
+        Ok((crc, size, self.writer.count - start))
+    }
+
+    /// Writes the central directory and returns the inner writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let mut answer = Vec::new();
+        let central_directory_offset = self.writer.count;
+        for record in &self.records {
+            write_central_directory_record(&mut answer, record, self.zip64_limit);
+        }
+        let central_directory_size = answer.len() as u64;
+
+        let number_entries = self.records.len();
+        if number_entries >= ZIP64_ENTRIES_LIMIT || central_directory_size >= self.zip64_limit ||
+            central_directory_offset >= self.zip64_limit {
+            write_zip64_end_of_central_directory(&mut answer, central_directory_offset + central_directory_size,
+                                                 number_entries, central_directory_size, central_directory_offset);
+        }
+
+        let classic_number_entries = if number_entries >= ZIP64_ENTRIES_LIMIT {
+            ZIP64_ENTRIES_MARKER
+        } else {
+            number_entries as u16
+        };
+        push_u32(&mut answer, END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        push_u16(&mut answer, 0);
+        push_u16(&mut answer, 0);
+        push_u16(&mut answer, classic_number_entries);
+        push_u16(&mut answer, classic_number_entries);
+        push_u32_or_zip64(&mut answer, central_directory_size, self.zip64_limit);
+        push_u32_or_zip64(&mut answer, central_directory_offset, self.zip64_limit);
+        push_u16(&mut answer, 0);
+
+        self.writer.write_all(&answer)?;
+        self.writer.flush()?;
+
+        Ok(self.writer.writer)
+    }
+}
+
+fn find_end_of_central_directory(data: &[u8]) -> Result<usize, DecodeError> {
+    if data.len() < END_OF_CENTRAL_DIRECTORY_SIZE {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+
+    let last_start = data.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+    let first_start = last_start.saturating_sub(MAX_COMMENT_SIZE);
+    (first_start..=last_start)
+        .rev()
+        .find(|x| read_u32(data, *x) == Ok(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or(DecodeError::InvalidHeader("end of central directory not found"))
+}
+
+/// Replaces the values saturated in the record with the ones from its Zip64 extra field.
+fn apply_zip64_extra_field(record: &mut CentralDirectoryRecord, extra: &[u8]) -> Result<(), DecodeError> {
+    let mut index = 0;
+    while index + 4 <= extra.len() {
+        let id = read_u16(extra, index)?;
+        let size = read_u16(extra, index + 2)? as usize;
+        if id == ZIP64_EXTRA_FIELD_ID {
+            let field = extra.get(index + 4..index + 4 + size).ok_or(DecodeError::UnexpectedEnd)?;
+            let mut field_index = 0;
+            for value in [&mut record.size, &mut record.compressed_size, &mut record.offset] {
+                if *value == ZIP64_MARKER as u64 {
+                    *value = read_u64(field, field_index)?;
+                    field_index += 8;
+                }
+            }
+            return Ok(());
+        }
+        index += 4 + size;
+    }
+
+    Ok(())
+}
+
+/// Parses the central directory read as a whole.
+fn read_central_directory_records(data: &[u8], number_entries: usize)
+                                  -> Result<Vec<CentralDirectoryRecord>, DecodeError> {
+    let mut index = 0;
+    let mut records = Vec::with_capacity(number_entries.min(ZIP64_ENTRIES_LIMIT));
+    for _ in 0..number_entries {
+        if read_u32(data, index)? != CENTRAL_DIRECTORY_SIGNATURE {
+            return Err(DecodeError::InvalidHeader("broken central directory record"));
+        }
+
+        let name_size = read_u16(data, index + 28)? as usize;
+        let extra_size = read_u16(data, index + 30)? as usize;
+        let comment_size = read_u16(data, index + 32)? as usize;
+
+        let name_start = index + CENTRAL_DIRECTORY_RECORD_SIZE;
+        let name = data.get(name_start..name_start + name_size).ok_or(DecodeError::UnexpectedEnd)?;
+
+        let mut record = CentralDirectoryRecord {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: read_u16(data, index + 8)?,
+            method: read_u16(data, index + 10)?,
+            dos_time: read_u16(data, index + 12)?,
+            dos_date: read_u16(data, index + 14)?,
+            crc: read_u32(data, index + 16)?,
+            compressed_size: read_u32(data, index + 20)? as u64,
+            size: read_u32(data, index + 24)? as u64,
+            external_attributes: read_u32(data, index + 38)?,
+            offset: read_u32(data, index + 42)? as u64,
+        };
+        let extra_start = name_start + name_size;
+        let extra = data.get(extra_start..extra_start + extra_size).ok_or(DecodeError::UnexpectedEnd)?;
+        apply_zip64_extra_field(&mut record, extra)?;
+        records.push(record);
+
+        index = name_start + name_size + extra_size + comment_size;
+    }
+
+    Ok(records)
+}
+
+/// Writes the data of an entry, computing its CRC-32 and size on the way.
+struct CheckingWriter<W: Write> {
+    writer: W,
+    crc: u32,
+    size: u64,
+}
+
+impl<W: Write> Write for CheckingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.writer.write(buf)?;
+        self.crc = update_crc32(self.crc, &buf[..size]);
+        self.size += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Decodes the deflate stream at the start of the reader, the data after its end is not used.
+fn inflate_entry<R: Read, W: Write>(mut reader: R, variant: DeflateVariant, output: &mut W)
+                                    -> Result<(), DecodeError> {
+    let mut inflater = Inflater::with_dictionary(&[], variant);
+    let mut input = vec![0; BUFFER_SIZE];
+    let mut output_buffer = vec![0; BUFFER_SIZE];
+    let mut input_start = 0;
+    let mut input_end = 0;
+
+    loop {
+        let (total_in, total_out) = (inflater.total_in(), inflater.total_out());
+        let status = inflater.feed(&input[input_start..input_end], &mut output_buffer)?;
+        input_start += (inflater.total_in() - total_in) as usize;
+        output.write_all(&output_buffer[..(inflater.total_out() - total_out) as usize])?;
+
+        match status {
+            Status::NeedsInput => {
+                input_start = 0;
+                input_end = reader.read(&mut input)?;
+                if input_end == 0 {
+                    return Err(DecodeError::UnexpectedEnd);
+                }
+            }
+            Status::OutputFull => {}
+            Status::Done => return Ok(()),
+        }
+    }
+}
+
+/// Reads a zip archive that lies between `start` and `end` of the reader, a self-extracting
+/// archive has the stub before it. Only the central directory is read as a whole, the data of
+/// an entry is decoded while it is read.
+pub struct ZipReader<R: Read + Seek> {
+    reader: R,
+    start: u64,
+    end: u64,
+}
+
+impl<R: Read + Seek> ZipReader<R> {
+    /// Nothing is read until the central directory is requested.
+    pub fn new(reader: R, start: u64, end: u64) -> ZipReader<R> {
+        ZipReader { reader, start, end }
+    }
+
+    /// Reads `size` bytes at `offset` from the start of the archive.
+    fn read_at(&mut self, offset: u64, size: usize) -> Result<Vec<u8>, DecodeError> {
+        if offset.checked_add(size as u64).is_none_or(|x| x > self.end - self.start) {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let mut answer = vec![0; size];
+        self.reader.seek(SeekFrom::Start(self.start + offset))?;
+        self.reader.read_exact(&mut answer)?;
+
+        Ok(answer)
+    }
+
+    /// Returns the number of entries, the size and the offset of the central directory.
+    fn read_end_of_central_directory(&mut self) -> Result<(usize, u64, u64), DecodeError> {
+        let archive_size = self.end - self.start;
+        let tail_size = archive_size.min((ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE + MAX_COMMENT_SIZE +
+            END_OF_CENTRAL_DIRECTORY_SIZE) as u64);
+        let tail = self.read_at(archive_size - tail_size, tail_size as usize)?;
+        let end_index = find_end_of_central_directory(&tail)?;
+
+        if end_index >= ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE {
+            let locator_index = end_index - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE;
+            if read_u32(&tail, locator_index)? == ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE {
+                let zip64_end = self.read_at(read_u64(&tail, locator_index + 8)?, ZIP64_END_OF_CENTRAL_DIRECTORY_SIZE)?;
+                if read_u32(&zip64_end, 0)? != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+                    return Err(DecodeError::InvalidHeader("zip64 end of central directory not found"));
+                }
+
+                return Ok((read_u64(&zip64_end, 32)? as usize, read_u64(&zip64_end, 40)?, read_u64(&zip64_end, 48)?));
+            }
+        }
+
+        Ok((read_u16(&tail, end_index + 10)? as usize, read_u32(&tail, end_index + 12)? as u64,
+            read_u32(&tail, end_index + 16)? as u64))
+    }
+
+    /// Returns the records of all entries in the order of the central directory.
+    pub fn read_central_directory(&mut self) -> Result<Vec<CentralDirectoryRecord>, DecodeError> {
+        let (number_entries, size, offset) = self.read_end_of_central_directory()?;
+        let data = self.read_at(offset, size as usize)?;
+
+        read_central_directory_records(&data, number_entries)
+    }
+
+    /// Writes the data of the entry to `output` while decoding it. The size and the CRC-32 are
+    /// checked at the end, after the data was written.
+    pub fn read_entry<W: Write>(&mut self, record: &CentralDirectoryRecord, output: W) -> Result<(), DecodeError> {
+        if record.flags & FLAG_ENCRYPTED != 0 {
+            return Err(DecodeError::InvalidHeader("encrypted entries are not supported"));
+        }
+
+        let header = self.read_at(record.offset, LOCAL_FILE_HEADER_SIZE)?;
+        if read_u32(&header, 0)? != LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(DecodeError::InvalidHeader("broken local file header"));
+        }
+
+        let name_size = read_u16(&header, 26)? as u64;
+        let extra_size = read_u16(&header, 28)? as u64;
+        let data_start = record.offset + (LOCAL_FILE_HEADER_SIZE as u64) + name_size + extra_size;
+        if data_start.checked_add(record.compressed_size).is_none_or(|x| x > self.end - self.start) {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        self.reader.seek(SeekFrom::Start(self.start + data_start))?;
+        let mut compressed = self.reader.by_ref().take(record.compressed_size);
+
+        let mut output = CheckingWriter { writer: output, crc: 0, size: 0 };
+        match record.method {
+            METHOD_STORED => {
+                std::io::copy(&mut compressed, &mut output)?;
+            }
+            METHOD_DEFLATE => inflate_entry(compressed, DeflateVariant::Deflate, &mut output)?,
+            METHOD_DEFLATE64 => inflate_entry(compressed, DeflateVariant::Deflate64, &mut output)?,
+            method => return Err(DecodeError::UnsupportedMethod(method))
+        }
+        output.flush()?;
+
+        if output.size != record.size {
+            return Err(DecodeError::SizeMismatch);
+        }
+        if output.crc != record.crc {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the entry below `destination` and creates the directories on its path. Names
+    /// that leave the destination are refused, the file of an entry that fails its checks is
+    /// removed.
+    pub fn extract_entry(&mut self, record: &CentralDirectoryRecord, destination: &Path) -> Result<(), DecodeError> {
+        let output_path = destination.join(extraction_path(&record.name)
+            .ok_or(DecodeError::InvalidHeader("entry name leaves the destination"))?);
+        if record.is_directory() {
+            fs::create_dir_all(output_path)?;
+            return Ok(());
+        }
+
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let writer = BufWriter::new(File::create(&output_path)?);
+        if let Err(e) = self.read_entry(record, writer) {
+            let _ = fs::remove_file(&output_path);
+            return Err(e);
+        }
+        set_unix_mode(&output_path, record.unix_mode())?;
+
+        Ok(())
+    }
+}
+
+/// Returns None for names that leave the destination or that name the destination itself.
+fn extraction_path(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    let is_inside = path.components().all(|x| matches!(x, Component::Normal(_) | Component::CurDir));
+    let has_name = path.components().any(|x| matches!(x, Component::Normal(_)));
+    if is_inside && has_name {
+        Some(path.to_path_buf())
+    } else {
+        None
+    }
+}
+
+/// Only the permission bits are applied, the setuid, setgid and sticky bits of an archive
+/// are not trusted, as in Info-ZIP `unzip`.
+#[cfg(unix)]
+fn set_unix_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if mode & 0o777 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_unix_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_data::random_bytes;
+
+    // low enough to reach the Zip64 fields with small files
+    const TEST_ZIP64_LIMIT: u64 = 0x10000;
+
+
+    fn file_entry(name: &str, size: usize) -> ZipEntry {
+        ZipEntry { name: name.to_string(), size: size as u64, mtime: 0, mode: 0o644, is_directory: false }
+    }
+
+    fn read_entries(archive: &[u8], start: u64) -> Vec<(CentralDirectoryRecord, Result<Vec<u8>, DecodeError>)> {
+        let mut reader = ZipReader::new(Cursor::new(archive), start, archive.len() as u64);
+        let records = reader.read_central_directory().unwrap();
+        records.into_iter().map(|record| {
+            let mut data = Vec::new();
+            let result = reader.read_entry(&record, &mut data).map(|_| data);
+            (record, result)
+        }).collect()
+    }
+
+    #[test]
+    fn names_stay_inside_the_destination() {
+        assert_eq!(extraction_path("dir/./file.txt"), Some(PathBuf::from("dir/./file.txt")));
+        assert_eq!(extraction_path("dir/"), Some(PathBuf::from("dir")));
+        for name in ["", ".", "./", "../file.txt", "dir/../../file.txt", "/etc/passwd"] {
+            assert_eq!(extraction_path(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn zip64_sizes_and_offsets_are_read_back() {
+        // incompressible data over the lowered limit, so the entries after it start past the limit too
+        let large = random_bytes(TEST_ZIP64_LIMIT as usize + 1000, 1);
+        let small = b"small file".to_vec();
+        let directory = ZipEntry { name: "dir/".to_string(), size: 0, mtime: 0, mode: 0o755, is_directory: true };
+
+        let mut writer = ZipWriter::with_zip64_limit(Vec::new(), DeflateVariant::Deflate, TEST_ZIP64_LIMIT);
+        writer.add_entry(&file_entry("large", large.len()), large.as_slice()).unwrap();
+        writer.add_entry(&directory, std::io::empty()).unwrap();
+        writer.add_entry(&file_entry("dir/small", small.len()), small.as_slice()).unwrap();
+        let archive = writer.finish().unwrap();
+
+        assert_eq!(read_u16(&archive, 28).unwrap(), 4 + ZIP64_EXTRA_FIELD_SIZE);
+        let end_index = archive.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+        assert_eq!(read_u32(&archive, end_index - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE).unwrap(),
+                   ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE);
+        assert_eq!(read_u32(&archive, end_index + 16).unwrap(), ZIP64_MARKER);
+
+        let entries = read_entries(&archive, 0);
+        assert_eq!(entries.len(), 3);
+        let (large_record, large_data) = &entries[0];
+        assert_eq!(large_record.size, large.len() as u64);
+        assert!(large_record.compressed_size >= TEST_ZIP64_LIMIT);
+        assert_eq!(large_data.as_ref().unwrap(), &large);
+        assert!(entries[1].0.is_directory());
+        let (small_record, small_data) = &entries[2];
+        assert!(small_record.offset >= TEST_ZIP64_LIMIT);
+        assert_eq!(small_data.as_ref().unwrap(), &small);
+    }
+
+    #[test]
+    fn archive_after_stub_is_read() {
+        let data = b"deflate64 deflate64 deflate64".repeat(100);
+        let mut writer = ZipWriter::new(b"stub".to_vec(), DeflateVariant::Deflate64);
+        writer.add_entry(&file_entry("a", data.len()), data.as_slice()).unwrap();
+        let archive = writer.finish().unwrap();
+
+        let entries = read_entries(&archive, 4);
+        assert_eq!(entries[0].0.method, METHOD_DEFLATE64);
+        assert_eq!(entries[0].1.as_ref().unwrap(), &data);
+    }
+
+    #[test]
+    fn broken_entries_are_reported() {
+        let data = random_bytes(5000, 1);
+        let mut writer = ZipWriter::new(Vec::new(), DeflateVariant::Deflate);
+        writer.add_entry(&file_entry("a", data.len()), data.as_slice()).unwrap();
+        let archive = writer.finish().unwrap();
+
+        let mut corrupted = archive.clone();
+        corrupted[LOCAL_FILE_HEADER_SIZE + 1 + 1000] ^= 1;
+        assert!(matches!(read_entries(&corrupted, 0)[0].1, Err(DecodeError::ChecksumMismatch)));
+
+        let entries = read_entries(&archive, 0);
+        let mut reader = ZipReader::new(Cursor::new(&archive[..2000]), 0, 2000);
+        assert!(matches!(reader.read_entry(&entries[0].0, Vec::new()), Err(DecodeError::UnexpectedEnd)));
+    }
+
+    #[test]
+    fn grown_file_is_rejected() {
+        let data = random_bytes(TEST_ZIP64_LIMIT as usize, 1);
+        let mut writer = ZipWriter::with_zip64_limit(Vec::new(), DeflateVariant::Deflate, TEST_ZIP64_LIMIT);
+        let error = writer.add_entry(&file_entry("a", 10), data.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn zip64_fields_start_at_the_limit() {
+        // the largest declared size whose compressed data surely fits into a classic header
+        let mut size = ZIP64_LIMIT;
+        while max_compressed_size(size) >= ZIP64_LIMIT {
+            size -= 1;
+        }
+        for (size, is_zip64) in [(size, false), (size + 1, true)] {
+            let mut writer = ZipWriter::new(Vec::new(), DeflateVariant::Deflate);
+            writer.add_entry(&ZipEntry { size, ..file_entry("a", 0) }, std::io::empty()).unwrap();
+            let archive = writer.finish().unwrap();
+            assert_eq!(read_u16(&archive, 28).unwrap() != 0, is_zip64, "declared size {}", size);
+        }
+
+        for (value, is_zip64) in [(ZIP64_LIMIT - 1, false), (ZIP64_LIMIT, true)] {
+            let record = CentralDirectoryRecord {
+                name: "a".to_string(),
+                flags: 0,
+                method: METHOD_STORED,
+                dos_time: 0,
+                dos_date: 0,
+                crc: 0,
+                compressed_size: value,
+                size: value,
+                external_attributes: 0,
+                offset: value,
+            };
+            let mut data = Vec::new();
+            write_central_directory_record(&mut data, &record, ZIP64_LIMIT);
+            assert_eq!(read_u32(&data, 42).unwrap() == ZIP64_MARKER, is_zip64);
+
+            let records = read_central_directory_records(&data, 1).unwrap();
+            assert_eq!((records[0].size, records[0].compressed_size, records[0].offset), (value, value, value));
+        }
+    }
+
+    #[test]
+    fn zip64_entry_count_is_read_back() {
+        for (number_entries, is_zip64) in [(ZIP64_ENTRIES_LIMIT - 1, false), (ZIP64_ENTRIES_LIMIT, true)] {
+            let mut writer = ZipWriter::new(Vec::new(), DeflateVariant::Deflate);
+            for i in 0..number_entries {
+                let entry = ZipEntry { name: format!("{}/", i), size: 0, mtime: 0, mode: 0o755, is_directory: true };
+                writer.add_entry(&entry, std::io::empty()).unwrap();
+            }
+            let archive = writer.finish().unwrap();
+
+            let end_index = archive.len() - END_OF_CENTRAL_DIRECTORY_SIZE;
+            assert_eq!(read_u16(&archive, end_index + 10).unwrap() == ZIP64_ENTRIES_MARKER, is_zip64);
+            assert_eq!(read_u32(&archive, end_index - ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIZE).unwrap() ==
+                           ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE, is_zip64);
+
+            let mut reader = ZipReader::new(Cursor::new(&archive), 0, archive.len() as u64);
+            let records = reader.read_central_directory().unwrap();
+            assert_eq!(records.len(), number_entries);
+            assert_eq!(records.last().unwrap().name, format!("{}/", number_entries - 1));
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! The zlib format (RFC 1950): a two-byte header, an optional preset dictionary id, a
+//! deflate stream and the Adler-32 of the data.
+
+use crate::adler32::adler32;
+use crate::bin_num::bin_to_bytes;
+use crate::error::DecodeError;
+use crate::{deflate_encoding_with_level, deflate_stream_decoding_with_dictionary, CompressionLevel, DeflateVariant};
+
+const DEFLATE_METHOD: u8 = 8;
+const MAX_WINDOW_INFO: u8 = 7;
+const FLAG_DICTIONARY: u8 = 0x20;
+const LEVEL_DEFAULT: u8 = 2;
+const LEVEL_MAXIMUM: u8 = 3;
+
+/// Returns true if the first two bytes are a valid zlib header for deflate.
+pub fn is_zlib(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] & 0x0f == DEFLATE_METHOD && data[0] >> 4 <= MAX_WINDOW_INFO &&
+        u16::from_be_bytes([data[0], data[1]]).is_multiple_of(31)
+}
+
+fn zlib_header(window_info: u8, level: u8, has_dictionary: bool) -> [u8; 2] {
+    let cmf = (window_info << 4) | DEFLATE_METHOD;
+    let mut flg = level << 6;
+    if has_dictionary {
+        flg |= FLAG_DICTIONARY;
+    }
+    flg += ((31 - u16::from_be_bytes([cmf, flg]) % 31) % 31) as u8;
+
+    [cmf, flg]
+}
+
+/// Compresses the data with the default compression level. With a dictionary, back
+/// references may point into it and the header holds its Adler-32.
+pub fn zlib_encoding(data: Vec<u8>, dictionary: Option<&[u8]>) -> Vec<u8> {
+    zlib_encoding_with_level(data, dictionary, CompressionLevel::Default)
+}
+
+/// Like [`zlib_encoding`], the level is also recorded in the header.
+pub fn zlib_encoding_with_level(data: Vec<u8>, dictionary: Option<&[u8]>, level: CompressionLevel) -> Vec<u8> {
+    let checksum = adler32(data.as_slice());
+    let header_level = match level {
+        CompressionLevel::Default => LEVEL_DEFAULT,
+        CompressionLevel::Best => LEVEL_MAXIMUM,
+    };
+
+    let mut answer = Vec::new();
+    answer.extend(zlib_header(MAX_WINDOW_INFO, header_level, dictionary.is_some()));
+    if let Some(dictionary) = dictionary {
+        answer.extend(adler32(dictionary).to_be_bytes());
+    }
+    answer.extend(bin_to_bytes(&deflate_encoding_with_level(data, dictionary.unwrap_or_default(), level,
+                                                            DeflateVariant::Deflate)));
+    answer.extend(checksum.to_be_bytes());
+
+    answer
+}
+
+/// A dictionary given for a stream that was compressed without one is ignored.
+pub fn zlib_decoding(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, DecodeError> {
+    Ok(zlib_stream_decoding(data, dictionary)?.0)
+}
+
+/// Also returns the size of the stream, the data after it is ignored.
+pub(crate) fn zlib_stream_decoding(data: &[u8], dictionary: Option<&[u8]>) -> Result<(Vec<u8>, usize), DecodeError> {
+    if data.len() < 2 {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    if !is_zlib(data) {
+        return Err(DecodeError::InvalidHeader("not a zlib stream"));
+    }
+
+    let mut header_size = 2;
+    let mut used_dictionary: &[u8] = &[];
+    if data[1] & FLAG_DICTIONARY != 0 {
+        let dictionary_id = data.get(2..6).ok_or(DecodeError::UnexpectedEnd)?;
+        let dictionary = dictionary.ok_or(DecodeError::DictionaryRequired)?;
+        if u32::from_be_bytes([dictionary_id[0], dictionary_id[1], dictionary_id[2], dictionary_id[3]]) !=
+            adler32(dictionary) {
+            return Err(DecodeError::DictionaryMismatch);
+        }
+        header_size += 4;
+        used_dictionary = dictionary;
+    }
+
+    let (answer, deflate_size) = deflate_stream_decoding_with_dictionary(&data[header_size..], used_dictionary,
+                                                                         DeflateVariant::Deflate)?;
+
+    let trailer_start = header_size + deflate_size;
+    let checksum = data.get(trailer_start..trailer_start + 4).ok_or(DecodeError::UnexpectedEnd)?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(answer.as_slice()) {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok((answer, trailer_start + 4))
+}
+
+/// The result is decoded once more to make sure it gives back the same data.
+pub fn zlib_recompression(data: &[u8], dictionary: Option<&[u8]>) -> Result<Vec<u8>, DecodeError> {
+    let original = zlib_decoding(data, dictionary)?;
+    let used_dictionary = if data[1] & FLAG_DICTIONARY != 0 { dictionary } else { None };
+    let answer = zlib_encoding_with_level(original.clone(), used_dictionary, CompressionLevel::Best);
+
+    if zlib_decoding(answer.as_slice(), used_dictionary)? != original {
+        return Err(DecodeError::ChecksumMismatch);
+    }
+
+    Ok(answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dictionary_must_match() {
+        let dictionary = b"common words of the messages".as_slice();
+        let data = b"the messages share common words".to_vec();
+        let compressed = zlib_encoding(data.clone(), Some(dictionary));
+
+        assert_eq!(zlib_decoding(&compressed, Some(dictionary)).unwrap(), data);
+        assert_eq!(zlib_decoding(&compressed, None), Err(DecodeError::DictionaryRequired));
+        assert_eq!(zlib_decoding(&compressed, Some(b"other words")), Err(DecodeError::DictionaryMismatch));
+    }
+}
@@ -0,0 +1,133 @@
+//! Checks the codecs against zlib and gzip: streams they wrote are decoded, and streams
+//! written here are decoded by the system `gzip` and by Python's `zlib` module. A check
+//! whose tool is not installed is skipped.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use dzip::gzip::{gzip_encoding_with_level, GzipHeader};
+use dzip::zlib::zlib_encoding_with_level;
+use dzip::CompressionLevel;
+
+#[path = "../src/test_data.rs"]
+mod test_data;
+
+use test_data::random_bytes;
+
+const TEXT: &[u8] = b"The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. The quick brown fox jumps over the lazy dog. Pack my box with five dozen liquor jugs! 0123456789";
+
+// zlib.compress(TEXT, 0): stored blocks
+const ZLIB_STORED: [u8; 197] = [
+    0x78, 0x01, 0x01, 0xba, 0x00, 0x45, 0xff, 0x54, 0x68, 0x65, 0x20, 0x71, 0x75, 0x69, 0x63, 0x6b,
+    0x20, 0x62, 0x72, 0x6f, 0x77, 0x6e, 0x20, 0x66, 0x6f, 0x78, 0x20, 0x6a, 0x75, 0x6d, 0x70, 0x73,
+    0x20, 0x6f, 0x76, 0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20, 0x6c, 0x61, 0x7a, 0x79, 0x20, 0x64,
+    0x6f, 0x67, 0x2e, 0x20, 0x54, 0x68, 0x65, 0x20, 0x71, 0x75, 0x69, 0x63, 0x6b, 0x20, 0x62, 0x72,
+    0x6f, 0x77, 0x6e, 0x20, 0x66, 0x6f, 0x78, 0x20, 0x6a, 0x75, 0x6d, 0x70, 0x73, 0x20, 0x6f, 0x76,
+    0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20, 0x6c, 0x61, 0x7a, 0x79, 0x20, 0x64, 0x6f, 0x67, 0x2e,
+    0x20, 0x54, 0x68, 0x65, 0x20, 0x71, 0x75, 0x69, 0x63, 0x6b, 0x20, 0x62, 0x72, 0x6f, 0x77, 0x6e,
+    0x20, 0x66, 0x6f, 0x78, 0x20, 0x6a, 0x75, 0x6d, 0x70, 0x73, 0x20, 0x6f, 0x76, 0x65, 0x72, 0x20,
+    0x74, 0x68, 0x65, 0x20, 0x6c, 0x61, 0x7a, 0x79, 0x20, 0x64, 0x6f, 0x67, 0x2e, 0x20, 0x50, 0x61,
+    0x63, 0x6b, 0x20, 0x6d, 0x79, 0x20, 0x62, 0x6f, 0x78, 0x20, 0x77, 0x69, 0x74, 0x68, 0x20, 0x66,
+    0x69, 0x76, 0x65, 0x20, 0x64, 0x6f, 0x7a, 0x65, 0x6e, 0x20, 0x6c, 0x69, 0x71, 0x75, 0x6f, 0x72,
+    0x20, 0x6a, 0x75, 0x67, 0x73, 0x21, 0x20, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38,
+    0x39, 0x74, 0x70, 0x41, 0x2d,
+];
+
+// zlib.compress(TEXT, 9): a dynamic block
+const ZLIB_DYNAMIC: [u8; 98] = [
+    0x78, 0xda, 0xb5, 0xcb, 0xd9, 0x15, 0x40, 0x30, 0x14, 0x45, 0xd1, 0x56, 0xae, 0x06, 0x2c, 0xf3,
+    0xd0, 0x85, 0x0f, 0x0d, 0x04, 0x41, 0x4c, 0x8f, 0x90, 0x20, 0xd5, 0x7b, 0x4d, 0xf8, 0x3e, 0xfb,
+    0xd4, 0xa3, 0xc4, 0x61, 0x54, 0x3b, 0xa3, 0xd1, 0x74, 0x6f, 0xe8, 0xe9, 0xc1, 0x64, 0xd6, 0xfd,
+    0x04, 0x59, 0xa9, 0x71, 0x71, 0x5e, 0x84, 0x7b, 0xd1, 0xd1, 0xe0, 0xa3, 0xfe, 0x0d, 0x57, 0x82,
+    0xdd, 0xfa, 0xa2, 0x61, 0x74, 0xab, 0x6b, 0x44, 0xaf, 0xac, 0xe4, 0xe4, 0xe4, 0x86, 0x45, 0x1d,
+    0x86, 0x34, 0xbf, 0xc3, 0xe9, 0x21, 0x08, 0xa3, 0x38, 0x49, 0xb3, 0xbc, 0x28, 0x3f, 0x74, 0x70,
+    0x41, 0x2d,
+];
+
+// zlib.compress(b"hello hello hello", 9): a fixed block
+const ZLIB_FIXED: [u8; 16] = [
+    0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00, 0x3a, 0x2e, 0x06, 0x7d,
+];
+
+// gzip of TEXT named fox.txt
+const GZIP_NAMED: [u8; 118] = [
+    0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x66, 0x6f, 0x78, 0x2e, 0x74, 0x78,
+    0x74, 0x00, 0xb5, 0xcb, 0xd9, 0x15, 0x40, 0x30, 0x14, 0x45, 0xd1, 0x56, 0xae, 0x06, 0x2c, 0xf3,
+    0xd0, 0x85, 0x0f, 0x0d, 0x04, 0x41, 0x4c, 0x8f, 0x90, 0x20, 0xd5, 0x7b, 0x4d, 0xf8, 0x3e, 0xfb,
+    0xd4, 0xa3, 0xc4, 0x61, 0x54, 0x3b, 0xa3, 0xd1, 0x74, 0x6f, 0xe8, 0xe9, 0xc1, 0x64, 0xd6, 0xfd,
+    0x04, 0x59, 0xa9, 0x71, 0x71, 0x5e, 0x84, 0x7b, 0xd1, 0xd1, 0xe0, 0xa3, 0xfe, 0x0d, 0x57, 0x82,
+    0xdd, 0xfa, 0xa2, 0x61, 0x74, 0xab, 0x6b, 0x44, 0xaf, 0xac, 0xe4, 0xe4, 0xe4, 0x86, 0x45, 0x1d,
+    0x86, 0x34, 0xbf, 0xc3, 0xe9, 0x21, 0x08, 0xa3, 0x38, 0x49, 0xb3, 0xbc, 0x28, 0x3f, 0x51, 0xe2,
+    0x5f, 0x85, 0xba, 0x00, 0x00, 0x00,
+];
+
+fn corpus() -> Vec<Vec<u8>> {
+    let random = random_bytes(30000, 1);
+    let source = [include_str!("../src/lib.rs"), include_str!("../src/main.rs"), include_str!("../src/stream.rs")]
+        .concat().into_bytes();
+
+    vec![Vec::new(), b"a".to_vec(), TEXT.to_vec(), vec![0; 100000], random, source]
+}
+
+/// Runs the tool on the input, None if it is not installed.
+fn run(program: &str, args: &[&str], input: &[u8]) -> Option<Vec<u8>> {
+    let mut child = Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().ok()?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "{} failed", program);
+
+    Some(output.stdout)
+}
+
+fn python_zlib_decoding(data: &[u8], wbits: i32) -> Option<Vec<u8>> {
+    let script = format!("import sys, zlib; sys.stdout.buffer.write(zlib.decompress(sys.stdin.buffer.read(), {}))", wbits);
+    run("python3", &["-c", &script], data)
+}
+
+#[test]
+fn decodes_zlib_vectors() {
+    assert_eq!(dzip::zlib_decompress(&ZLIB_STORED, None).unwrap(), TEXT);
+    assert_eq!(dzip::zlib_decompress(&ZLIB_DYNAMIC, None).unwrap(), TEXT);
+    assert_eq!(dzip::zlib_decompress(&ZLIB_FIXED, None).unwrap(), b"hello hello hello");
+    assert_eq!(dzip::decompress(&ZLIB_DYNAMIC).unwrap(), TEXT);
+}
+
+#[test]
+fn decodes_gzip_vectors() {
+    assert_eq!(dzip::decompress(&GZIP_NAMED).unwrap(), TEXT);
+}
+
+#[test]
+fn gzip_decodes_gzip_output() {
+    for data in corpus() {
+        for level in [CompressionLevel::Default, CompressionLevel::Best] {
+            let compressed = gzip_encoding_with_level(data.clone(), &GzipHeader::default(), level);
+            let Some(answer) = run("gzip", &["-dc"], &compressed) else {
+                eprintln!("gzip is not installed, skipped");
+                return;
+            };
+            assert_eq!(answer, data);
+        }
+    }
+}
+
+#[test]
+fn zlib_decodes_zlib_and_raw_deflate_output() {
+    for data in corpus() {
+        let compressed = zlib_encoding_with_level(data.clone(), None, CompressionLevel::Default);
+        let Some(answer) = python_zlib_decoding(&compressed, 15) else {
+            eprintln!("python3 is not installed, skipped");
+            return;
+        };
+        assert_eq!(answer, data);
+
+        // the payload of a .dzip file is a raw deflate stream
+        let mut encoder = dzip::DzipEncoder::new(Vec::new());
+        encoder.write_all(&data).unwrap();
+        let raw = encoder.finish().unwrap();
+        assert_eq!(python_zlib_decoding(&raw, -15).unwrap(), data);
+    }
+}